@@ -0,0 +1,79 @@
+//! Compares [Layout::get_item]'s binary search with going through every row and item, on a dense
+//! synthetic layout. Run with `cargo bench`.
+
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use numpad_driver::layout::{Layout, Rect, Row, RowItem};
+
+const ROWS: usize = 100;
+const COLUMNS: usize = 100;
+const SIZE: usize = 10_000;
+/// Coordinates to look up, spread over the whole layout so that both hits and margins are included.
+const STEP: usize = 37;
+
+/// What get_item did before the layouts were sorted: the first item containing the point.
+fn linear_get_item<T>(layout: &Layout<T>, x: usize, y: usize) -> Option<&T> {
+    layout
+        .rows()
+        .iter()
+        .filter(|row| row.min_y() <= y && y <= row.max_y())
+        .flat_map(Row::items)
+        .find(|item| item.left_x() <= x && x <= item.right_x())
+        .map(RowItem::item)
+}
+
+fn time(name: &str, lookup: impl Fn(usize, usize) -> Option<usize>) -> Duration {
+    let points: Vec<(usize, usize)> = (0..SIZE)
+        .step_by(STEP)
+        .flat_map(|y| (0..SIZE).step_by(STEP).map(move |x| (x, y)))
+        .collect();
+    let mut best = Duration::MAX;
+    for _ in 0..10 {
+        let start = Instant::now();
+        for &(x, y) in &points {
+            black_box(lookup(black_box(x), black_box(y)));
+        }
+        best = best.min(start.elapsed());
+    }
+    println!(
+        "{:<14} {:>10.1?} for {} lookups, {:>7.1?} each",
+        name,
+        best,
+        points.len(),
+        best / points.len() as u32
+    );
+    best
+}
+
+fn main() {
+    let cells: Vec<Vec<Option<usize>>> = (0..ROWS)
+        .map(|row| {
+            (0..COLUMNS)
+                .map(|column| Some(row * COLUMNS + column))
+                .collect()
+        })
+        .collect();
+    let cells: Vec<&[Option<usize>]> = cells.iter().map(Vec::as_slice).collect();
+    let area = Rect {
+        left: 0,
+        top: 0,
+        right: SIZE - 1,
+        bottom: SIZE - 1,
+    };
+    let layout = Layout::grid(&cells, area, 10);
+    layout.validate().unwrap();
+
+    let linear = time("linear scan", |x, y| {
+        linear_get_item(&layout, x, y).copied()
+    });
+    let binary = time("binary search", |x, y| layout.get_item(x, y).copied());
+    println!(
+        "{}x{} items: the binary search is {:.1} times as fast",
+        ROWS,
+        COLUMNS,
+        linear.as_secs_f64() / binary.as_secs_f64()
+    );
+}
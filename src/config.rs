@@ -0,0 +1,1030 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use evdev_rs::enums::EV_KEY;
+use thiserror::Error;
+use toml::{Table, Value};
+
+use crate::{
+    key_simulation::{InputId, UnicodeInput, parse_ev_key},
+    layout::{Action, Layout, LayoutError, Margins, Row, RowItem},
+    numpad_light::{
+        DEFAULT_ADDRESS, DEFAULT_GAMMA, DEFAULT_RETRIES, LightModel, MAX_BRIGHTNESS,
+        is_valid_address, is_valid_gamma,
+    },
+};
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("couldn't read the file: {}", .0)]
+    Io(#[from] std::io::Error),
+    #[error("invalid toml: {}", .0)]
+    Toml(#[from] toml::de::Error),
+    #[error("missing or invalid field `{}` in {}", .field, .location)]
+    InvalidField {
+        field: &'static str,
+        location: String,
+    },
+    #[error("unknown key {:?} in {}", .key, .location)]
+    UnknownKey { key: String, location: String },
+    #[error("invalid layout: {}", .0)]
+    InvalidLayout(#[from] LayoutError),
+    #[error("invalid value {:?} for environment variable {}", .value, .name)]
+    InvalidEnvVar { name: &'static str, value: String },
+}
+
+/// The keys [Config::decimal_key] can be.
+pub const DECIMAL_KEYS: [EV_KEY; 3] = [EV_KEY::KEY_DOT, EV_KEY::KEY_COMMA, EV_KEY::KEY_KPCOMMA];
+
+/// Two keys of the layout which, touched at the same time with two fingers, hold down other keys
+/// instead, e.g. KEY_1 and KEY_2 for Ctrl+C. `[[chords]]` in the config file, each with `keys`, the
+/// two layout keys, and `press`, the keys they hold down.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chord {
+    pub keys: (EV_KEY, EV_KEY),
+    /// Pressed in order while both fingers are down and released in reverse order.
+    pub press: Vec<EV_KEY>,
+}
+
+impl Chord {
+    /// Whether touching both keys, in either order, makes the chord.
+    pub fn matches(&self, first: EV_KEY, second: EV_KEY) -> bool {
+        self.keys == (first, second) || self.keys == (second, first)
+    }
+}
+
+/// Settings which can be tuned without recompiling, read from config.toml and then
+/// overridden by environment variables.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// How long a key needs to be touched before it's considered held.
+    /// `hold_ms` in the config file, `NUMPAD_HOLD_MS` in the environment.
+    pub hold_duration: Duration,
+    /// How far the finger needs to move from where it first touched for the touch to be a drag.
+    /// `drag_distance` in the config file, `NUMPAD_DRAG_DIST` in the environment.
+    pub drag_distance: f64,
+    /// How far past the edge of the item it touched the finger needs to be when it's lifted or held for the
+    /// tap or the hold to be on the item it's on instead, so that a finger resting on the border between two
+    /// items doesn't tap or hold either of them depending on the jitter. `key_hysteresis` in the config file.
+    pub key_hysteresis: f64,
+    /// How far a drag from the activation key needs to go for each brightness level it changes, or None
+    /// for every drag to change it by a single level. Every drag changes it by at least a level.
+    /// `brightness_drag_step` in the config file, where 0 means a single level.
+    pub brightness_drag_step: Option<f64>,
+    /// Whether a drag from the activation key changes the brightness as it goes, by a level for every
+    /// `brightness_drag_step` (or `drag_distance` if that's None) it travels up or down, rather than
+    /// once the finger is lifted. `live_brightness_drag` in the config file.
+    pub live_brightness_drag: bool,
+    /// Whether dragging down from the activation key makes the light brighter and dragging up darker,
+    /// rather than the other way around. `invert_brightness_drag` in the config file.
+    pub invert_brightness_drag: bool,
+    /// How long the numpad can go untouched while active before its light is turned off, or None to
+    /// never turn it off. `idle_timeout_s` in the config file, where 0 means never.
+    pub idle_timeout: Option<Duration>,
+    /// How long the numpad can go untouched while active before its light is dimmed to the lowest level,
+    /// ahead of being turned off after `idle_timeout`, or None to never dim it. `dim_timeout_s` in the
+    /// config file, where 0 means never.
+    pub dim_timeout: Option<Duration>,
+    /// How long after a key starts being held (see `hold_duration`) it starts repeating, or None to never repeat.
+    /// `repeat_delay_ms` in the config file, where 0 means never.
+    pub repeat_delay: Option<Duration>,
+    /// How often a held key repeats once it started repeating. `repeat_interval_ms` in the config file.
+    pub repeat_interval: Duration,
+    /// Whether held keys are repeated by the kernel, with `repeat_delay` and `repeat_interval` as the
+    /// keyboard device's EV_REP delay and period, instead of by the driver. `kernel_repeat` in the config file.
+    pub kernel_repeat: bool,
+    /// How large a contact needs to be, as a fraction of the largest contact the touchpad can report,
+    /// for it to be considered a palm, in which case the touch doesn't press any keys. None to never
+    /// reject palms. `palm_size` in the config file, where 0 means never.
+    /// Touches with multiple fingers never press keys either, since they're gestures.
+    pub palm_size: Option<f64>,
+    /// Strings which all need to be part of an input device's name for it to be used as the touchpad.
+    /// `touchpad_name` in the config file, `NUMPAD_TOUCHPAD_NAME` (comma separated) in the environment.
+    pub touchpad_name: Vec<String>,
+    /// Whether to toggle the system's NumLock along with the numpad, and start out active if it's on.
+    /// `sync_numlock` in the config file.
+    pub sync_numlock: bool,
+    /// The layout item which toggles the numpad when tapped and changes the brightness when dragged from,
+    /// as `TOGGLE_ACTIVE` items always do. `activation_key` in the config file, e.g. `"KEY_NUMLOCK"`,
+    /// which is there for layouts from before `TOGGLE_ACTIVE`.
+    pub activation_key: Action,
+    /// How long the activation key needs to be held to toggle the numpad, or None to toggle it by tapping.
+    /// `activation_hold_ms` in the config file, where 0 means tapping. While this is set, holding the
+    /// activation key doesn't do its hold item.
+    pub activation_hold: Option<Duration>,
+    /// How large the top right corner of the touchpad is, as a fraction of its width and height, from
+    /// which swiping down and to the left toggles the numpad, as on the stock firmware, or None to only
+    /// toggle it with the layout's items. `activation_swipe` in the config file, where 0 means never.
+    pub activation_swipe: Option<f64>,
+    /// How soon after tapping the activation key it needs to be tapped again for the numpad to toggle,
+    /// against toggling it by accident, or None to toggle it on every tap. `activation_double_tap_ms` in
+    /// the config file, where 0 means every tap.
+    pub activation_double_tap: Option<Duration>,
+    /// The program, followed by its arguments, to run when the activation key is held for longer than the
+    /// hold duration, e.g. a calculator, or None to do nothing. `activation_hold_command` in the config
+    /// file, e.g. `["gnome-calculator"]`. Items' hold items and `activation_hold` take precedence.
+    pub activation_hold_command: Option<Vec<String>>,
+    /// How `U+` items type their characters. `unicode_start` and `unicode_end` in the config file, the
+    /// keys to press together before and after the code point, e.g. `["KEY_LEFTCTRL", "KEY_LEFTSHIFT", "KEY_U"]`
+    /// and `["KEY_SPACE"]`, the default.
+    pub unicode_input: UnicodeInput,
+    /// The chords which two fingers can make on the active numpad.
+    pub chords: Vec<Chord>,
+    /// How soon after the first finger the second one needs to touch for the two to make a chord.
+    /// `chord_window_ms` in the config file.
+    pub chord_window: Duration,
+    /// How strongly (0.0 to 1.0) to vibrate the touchpad when a key is pressed, if it can vibrate,
+    /// or None to not vibrate. `haptic_strength` in the config file, where 0 means not to vibrate.
+    pub haptic_strength: Option<f64>,
+    /// Whether to briefly dim the light when a key is pressed. `key_flash` in the config file.
+    pub key_flash: bool,
+    /// How long changing the brightness takes to fade from the old level to the new one.
+    /// `fade_ms` in the config file.
+    pub fade_duration: Duration,
+    /// The key the builtin layout's decimal separator sends, one of [DECIMAL_KEYS], e.g. `KEY_COMMA` where
+    /// the decimal separator is a comma. Custom layouts name their keys themselves, so it doesn't change
+    /// them. `decimal_key` in the config file.
+    pub decimal_key: EV_KEY,
+    /// The gaps the builtin layout leaves between its keys and its rows. `key_margin_x` and
+    /// `key_margin_y` in the config file, which have to be at least 1.
+    pub margins: Margins,
+    /// The ids of the virtual keyboard, for telling it apart from other virtual devices.
+    /// `keyboard_bustype`, `keyboard_vendor`, `keyboard_product` and `keyboard_version` in the config file.
+    pub keyboard_id: InputId,
+    /// The I2C address of the numpad's light. `light_address` in the config file.
+    pub light_address: u16,
+    /// Which commands the numpad's light understands. `light_model` in the config file, by its name.
+    pub light_model: &'static LightModel,
+    /// How many times to retry writing to the light while the I2C bus is busy. `light_retries` in the
+    /// config file.
+    pub light_retries: u32,
+    /// The gamma brightness percentages are mapped to the light's levels with, see
+    /// [NumpadLight::set_gamma](crate::numpad_light::NumpadLight::set_gamma). `brightness_gamma` in the
+    /// config file.
+    pub brightness_gamma: f64,
+    /// How many times to blink the light when the numpad is activated. `activation_blinks` in the config file.
+    pub activation_blinks: u32,
+    /// How long the light stays off and then on for each blink. `blink_interval_ms` in the config file.
+    pub blink_interval: Duration,
+    /// How long the light takes to fade down and back up again while the numpad is active, for a breathing
+    /// effect, or None to keep it steady. `breathing_period_ms` in the config file, where 0 means steady.
+    pub breathing_period: Option<Duration>,
+    /// How far (0.0 to 1.0) the light fades down from its brightness while breathing, e.g. 0.5 for half
+    /// of it. `breathing_depth` in the config file.
+    pub breathing_depth: f64,
+    /// Whether tapping the active numpad with two fingers right clicks, like it does on the rest of the
+    /// touchpad. `two_finger_right_click` in the config file.
+    pub two_finger_right_click: bool,
+    /// How long a touch on a key has to stay put before the touchpad is grabbed for it, or None to grab
+    /// it right away. Until then, the touch also moves the pointer, so that moving a finger on the
+    /// active numpad always moves it, while a quick tap still presses the key; touching a key for
+    /// longer than that ends up grabbing the touchpad as usual. In exchange, a tap quicker than the
+    /// delay is never grabbed, so the touchpad's driver gets all of it too: the pointer may jitter, and
+    /// tap to click, if it's on, clicks along with the key. `hybrid_grab_delay_ms` in the config file,
+    /// where 0 means right away.
+    pub hybrid_grab_delay: Option<Duration>,
+    /// How soon after a key was tapped another tap of the same key is taken for the finger bouncing and
+    /// ignored, or None to never ignore taps. `debounce_ms` in the config file, where 0 means never.
+    pub debounce: Option<Duration>,
+    /// Whether to mirror the layouts horizontally, e.g. for left handed use. `mirror_layout` in the
+    /// config file.
+    pub mirror_layout: bool,
+    /// Whether to keep the light on while the numpad is inactive too, for seeing the printed keys
+    /// without pressing them. `light_always_on` in the config file.
+    pub light_always_on: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            hold_duration: Duration::from_millis(250),
+            drag_distance: 30.0,
+            // well below the drag distance, beyond which there's no tap anyways
+            key_hysteresis: 10.0,
+            // a drag across the builtin layout's rows goes through most of the levels
+            brightness_drag_step: Some(300.0),
+            live_brightness_drag: false,
+            invert_brightness_drag: false,
+            idle_timeout: Some(Duration::from_secs(5 * 60)),
+            dim_timeout: None,
+            repeat_delay: Some(Duration::from_millis(500)),
+            repeat_interval: Duration::from_millis(33),
+            kernel_repeat: false,
+            // fingers, even pressed flat, stay well below this
+            palm_size: Some(0.5),
+            touchpad_name: vec!["ASUF".to_string(), "Touchpad".to_string()],
+            sync_numlock: false,
+            activation_key: Action::Key(EV_KEY::KEY_NUMLOCK),
+            activation_hold: None,
+            activation_swipe: None,
+            activation_double_tap: None,
+            activation_hold_command: None,
+            unicode_input: UnicodeInput::default(),
+            chords: Vec::new(),
+            // fingers meant to touch at the same time land a few dozen milliseconds apart
+            chord_window: Duration::from_millis(100),
+            haptic_strength: None,
+            key_flash: false,
+            fade_duration: Duration::from_millis(200),
+            decimal_key: EV_KEY::KEY_DOT,
+            margins: Margins::DEFAULT,
+            keyboard_id: InputId::default(),
+            light_address: DEFAULT_ADDRESS,
+            light_model: LightModel::default_model(),
+            light_retries: DEFAULT_RETRIES,
+            brightness_gamma: DEFAULT_GAMMA,
+            activation_blinks: 0,
+            blink_interval: Duration::from_millis(100),
+            breathing_period: None,
+            breathing_depth: 0.5,
+            two_finger_right_click: false,
+            hybrid_grab_delay: None,
+            // well below how fast anyone taps the same key twice on purpose
+            debounce: Some(Duration::from_millis(50)),
+            mirror_layout: false,
+            light_always_on: false,
+        }
+    }
+}
+
+/// The directory holding the driver's configuration files, i.e. `$XDG_CONFIG_HOME/numpad_driver`
+/// or `~/.config/numpad_driver` if `XDG_CONFIG_HOME` isn't set.
+pub fn config_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("numpad_driver"))
+}
+
+/// The path of the layout file which is loaded at startup, if it exists.
+pub fn layout_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("layout.toml"))
+}
+
+/// The directory holding the layouts which can be switched to besides the main one, one
+/// `<name>.toml` file per layout.
+pub fn layouts_dir() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("layouts"))
+}
+
+/// The directory holding the state which is kept across restarts, i.e. `$XDG_STATE_HOME/numpad_driver`
+/// or `~/.local/state/numpad_driver` if `XDG_STATE_HOME` isn't set.
+pub fn state_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_STATE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state"))
+        })
+        .map(|dir| dir.join("numpad_driver"))
+}
+
+pub fn state_path() -> Option<PathBuf> {
+    state_dir().map(|dir| dir.join("state.toml"))
+}
+
+/// The path of the config file which is loaded at startup, if it exists.
+pub fn config_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("config.toml"))
+}
+
+/// Load a layout from a toml file. The file is expected to look like this:
+/// ```toml
+/// [[rows]]
+/// min_y = 200
+/// max_y = 680
+/// items = [
+///     { left_x = 330, right_x = 860, key = "KEY_7" },
+///     { left_x = 910, right_x = 1600, key = "8" },
+/// ]
+/// ```
+/// Optionally, a top level `size = [width, height]` can be given, in which case the coordinates are
+/// relative to that size rather than being the touchpad's raw coordinates
+/// (e.g. with `size = [100, 100]` they're percentages of the touchpad).
+/// A `key` (or `hold_key`) is any key name [parse_ev_key] takes, e.g. `"KEY_7"` or `"7"`, or one of
+/// these actions, see [Action]:
+/// - `"TOGGLE_ACTIVE"` toggles the numpad, and dragging from it changes the brightness
+/// - `"BRIGHTNESS_UP"` and `"BRIGHTNESS_DOWN"` change the light's brightness by a level
+/// - `"MOD_"` followed by a key name, e.g. `"MOD_LEFTCTRL"`, is a sticky modifier
+/// - `"NEXT_LAYOUT"` switches to the next layout, and `"SWITCH_LAYOUT_"` followed by an index, e.g.
+///   `"SWITCH_LAYOUT_0"` for the main layout, to that one
+/// - `"DEAD_ZONE"` does nothing, but doesn't move the pointer either
+/// - `"U+"` followed by a code point in hexadecimal, e.g. `"U+00B0"`, types that character
+pub fn load_layout(path: &Path) -> Result<Layout<Action>, ConfigError> {
+    parse_layout(&std::fs::read_to_string(path)?)
+}
+
+/// Write the layout in the format [load_layout] reads, creating the directory if needed.
+pub fn save_layout(layout: &Layout<Action>, path: &Path) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, layout_to_toml(layout))
+}
+
+pub fn layout_to_toml(layout: &Layout<Action>) -> String {
+    let coordinate = |value: usize| Value::from(value as i64);
+    let rows = layout
+        .rows()
+        .iter()
+        .map(|row| {
+            let items = row
+                .items()
+                .iter()
+                .map(|item| {
+                    let mut table = Table::new();
+                    table.insert("left_x".to_string(), coordinate(item.left_x()));
+                    table.insert("right_x".to_string(), coordinate(item.right_x()));
+                    table.insert("key".to_string(), Value::from(item.item().to_string()));
+                    if let Some(hold_item) = item.hold_item() {
+                        table.insert("hold_key".to_string(), Value::from(hold_item.to_string()));
+                    }
+                    Value::from(table)
+                })
+                .collect::<Vec<Value>>();
+            let mut table = Table::new();
+            table.insert("min_y".to_string(), coordinate(row.min_y()));
+            table.insert("max_y".to_string(), coordinate(row.max_y()));
+            table.insert("items".to_string(), Value::from(items));
+            Value::from(table)
+        })
+        .collect::<Vec<Value>>();
+    let mut table = Table::new();
+    if let Some((width, height)) = layout.size() {
+        table.insert(
+            "size".to_string(),
+            Value::from(vec![coordinate(width), coordinate(height)]),
+        );
+    }
+    table.insert("rows".to_string(), Value::from(rows));
+    table.to_string()
+}
+
+pub fn parse_layout(text: &str) -> Result<Layout<Action>, ConfigError> {
+    let table: Table = text.parse()?;
+    let rows = table
+        .get("rows")
+        .and_then(Value::as_array)
+        .ok_or(ConfigError::InvalidField {
+            field: "rows",
+            location: "the layout".to_string(),
+        })?;
+
+    let mut layout_rows = Vec::new();
+    for (row_index, row) in rows.iter().enumerate() {
+        let location = format!("row {}", row_index);
+        let row = row.as_table().ok_or(ConfigError::InvalidField {
+            field: "rows",
+            location: location.clone(),
+        })?;
+        let min_y = get_coordinate(row, "min_y", &location)?;
+        let max_y = get_coordinate(row, "max_y", &location)?;
+
+        let items =
+            row.get("items")
+                .and_then(Value::as_array)
+                .ok_or(ConfigError::InvalidField {
+                    field: "items",
+                    location: location.clone(),
+                })?;
+        let mut row_items = Vec::new();
+        for (item_index, item) in items.iter().enumerate() {
+            let location = format!("row {}, item {}", row_index, item_index);
+            let item = item.as_table().ok_or(ConfigError::InvalidField {
+                field: "items",
+                location: location.clone(),
+            })?;
+            let left_x = get_coordinate(item, "left_x", &location)?;
+            let right_x = get_coordinate(item, "right_x", &location)?;
+            let key_name =
+                item.get("key")
+                    .and_then(Value::as_str)
+                    .ok_or(ConfigError::InvalidField {
+                        field: "key",
+                        location: location.clone(),
+                    })?;
+            let parse_action = |name: &str| {
+                name.parse::<Action>().map_err(|_| ConfigError::UnknownKey {
+                    key: name.to_string(),
+                    location: location.clone(),
+                })
+            };
+            let mut row_item = RowItem::new(left_x, right_x, parse_action(key_name)?);
+            if let Some(hold_key) = item.get("hold_key") {
+                let hold_key_name = hold_key.as_str().ok_or(ConfigError::InvalidField {
+                    field: "hold_key",
+                    location: location.clone(),
+                })?;
+                row_item = row_item.with_hold_item(parse_action(hold_key_name)?);
+            }
+            row_items.push(row_item);
+        }
+        layout_rows.push(Row::new(min_y, max_y, row_items));
+    }
+
+    let mut layout = Layout::new(layout_rows);
+    if let Some(size) = table.get("size") {
+        let size = size
+            .as_array()
+            .filter(|size| size.len() == 2)
+            .and_then(|size| {
+                let width = usize::try_from(size[0].as_integer()?).ok()?;
+                let height = usize::try_from(size[1].as_integer()?).ok()?;
+                Some((width, height))
+            })
+            .ok_or(ConfigError::InvalidField {
+                field: "size",
+                location: "the layout".to_string(),
+            })?;
+        layout = layout.with_size(size.0, size.1);
+    }
+    layout.validate()?;
+    Ok(layout)
+}
+
+fn get_coordinate(
+    table: &Table,
+    field: &'static str,
+    location: &str,
+) -> Result<usize, ConfigError> {
+    table
+        .get(field)
+        .and_then(Value::as_integer)
+        .and_then(|value| usize::try_from(value).ok())
+        .ok_or(ConfigError::InvalidField {
+            field,
+            location: location.to_string(),
+        })
+}
+
+/// Load the config from a toml file; missing fields keep their default values.
+pub fn load_config(path: &Path) -> Result<Config, ConfigError> {
+    parse_config(&std::fs::read_to_string(path)?)
+}
+
+pub fn parse_config(text: &str) -> Result<Config, ConfigError> {
+    let table: Table = text.parse()?;
+    let mut config = Config::default();
+    if let Some(hold_duration) = get_duration(&table, "hold_ms", Duration::from_millis)? {
+        config.hold_duration = hold_duration;
+    }
+    if let Some(value) = table.get("drag_distance") {
+        config.drag_distance = as_float(value)
+            .filter(|distance| *distance >= 0.0)
+            .ok_or(invalid_config_field("drag_distance"))?;
+    }
+    if let Some(value) = table.get("key_hysteresis") {
+        config.key_hysteresis = as_float(value)
+            .filter(|distance| *distance >= 0.0)
+            .ok_or(invalid_config_field("key_hysteresis"))?;
+    }
+    if let Some(value) = table.get("brightness_drag_step") {
+        let step = as_float(value)
+            .filter(|step| *step >= 0.0)
+            .ok_or(invalid_config_field("brightness_drag_step"))?;
+        config.brightness_drag_step = Some(step).filter(|step| *step > 0.0);
+    }
+    if let Some(value) = table.get("invert_brightness_drag") {
+        config.invert_brightness_drag = value
+            .as_bool()
+            .ok_or(invalid_config_field("invert_brightness_drag"))?;
+    }
+    if let Some(value) = table.get("live_brightness_drag") {
+        config.live_brightness_drag = value
+            .as_bool()
+            .ok_or(invalid_config_field("live_brightness_drag"))?;
+    }
+    if let Some(idle_timeout) = get_duration(&table, "idle_timeout_s", Duration::from_secs)? {
+        config.idle_timeout = Some(idle_timeout).filter(|timeout| !timeout.is_zero());
+    }
+    if let Some(hybrid_grab_delay) =
+        get_duration(&table, "hybrid_grab_delay_ms", Duration::from_millis)?
+    {
+        config.hybrid_grab_delay = Some(hybrid_grab_delay).filter(|delay| !delay.is_zero());
+    }
+    if let Some(dim_timeout) = get_duration(&table, "dim_timeout_s", Duration::from_secs)? {
+        config.dim_timeout = Some(dim_timeout).filter(|timeout| !timeout.is_zero());
+    }
+    if let Some(repeat_delay) = get_duration(&table, "repeat_delay_ms", Duration::from_millis)? {
+        config.repeat_delay = Some(repeat_delay).filter(|delay| !delay.is_zero());
+    }
+    if let Some(repeat_interval) =
+        get_duration(&table, "repeat_interval_ms", Duration::from_millis)?
+    {
+        if repeat_interval.is_zero() {
+            return Err(invalid_config_field("repeat_interval_ms"));
+        }
+        config.repeat_interval = repeat_interval;
+    }
+    if let Some(activation_hold) =
+        get_duration(&table, "activation_hold_ms", Duration::from_millis)?
+    {
+        config.activation_hold = Some(activation_hold).filter(|hold| !hold.is_zero());
+    }
+    if let Some(activation_double_tap) =
+        get_duration(&table, "activation_double_tap_ms", Duration::from_millis)?
+    {
+        config.activation_double_tap =
+            Some(activation_double_tap).filter(|window| !window.is_zero());
+    }
+    if let Some(value) = table.get("activation_swipe") {
+        let activation_swipe = as_float(value)
+            .filter(|size| (0.0..=1.0).contains(size))
+            .ok_or(invalid_config_field("activation_swipe"))?;
+        config.activation_swipe = Some(activation_swipe).filter(|size| *size > 0.0);
+    }
+    if let Some(value) = table.get("activation_hold_command") {
+        config.activation_hold_command = Some(
+            value
+                .as_array()
+                .and_then(|parts| {
+                    parts
+                        .iter()
+                        .map(|part| part.as_str().map(str::to_string))
+                        .collect::<Option<Vec<String>>>()
+                })
+                .filter(|command| !command.is_empty())
+                .ok_or(invalid_config_field("activation_hold_command"))?,
+        );
+    }
+    for (name, keys) in [
+        ("unicode_start", &mut config.unicode_input.start),
+        ("unicode_end", &mut config.unicode_input.end),
+    ] {
+        if let Some(value) = table.get(name) {
+            *keys = value
+                .as_array()
+                .and_then(|keys| {
+                    keys.iter()
+                        .map(|key| key.as_str().and_then(parse_ev_key))
+                        .collect::<Option<Vec<EV_KEY>>>()
+                })
+                .ok_or(invalid_config_field(name))?;
+        }
+    }
+    if config.unicode_input.start.is_empty() {
+        return Err(invalid_config_field("unicode_start"));
+    }
+    if let Some(value) = table.get("chords") {
+        config.chords = value
+            .as_array()
+            .and_then(|chords| chords.iter().map(parse_chord).collect())
+            .ok_or(invalid_config_field("chords"))?;
+    }
+    if let Some(chord_window) = get_duration(&table, "chord_window_ms", Duration::from_millis)? {
+        config.chord_window = chord_window;
+    }
+    if let Some(debounce) = get_duration(&table, "debounce_ms", Duration::from_millis)? {
+        config.debounce = Some(debounce).filter(|debounce| !debounce.is_zero());
+    }
+    if let Some(value) = table.get("palm_size") {
+        let palm_size = as_float(value)
+            .filter(|size| (0.0..=1.0).contains(size))
+            .ok_or(invalid_config_field("palm_size"))?;
+        config.palm_size = Some(palm_size).filter(|size| *size > 0.0);
+    }
+    if let Some(value) = table.get("touchpad_name") {
+        config.touchpad_name = value
+            .as_array()
+            .and_then(|parts| {
+                parts
+                    .iter()
+                    .map(|part| part.as_str().map(str::to_string))
+                    .collect()
+            })
+            .ok_or(invalid_config_field("touchpad_name"))?;
+    }
+    if let Some(value) = table.get("kernel_repeat") {
+        config.kernel_repeat = value
+            .as_bool()
+            .ok_or(invalid_config_field("kernel_repeat"))?;
+    }
+    if let Some(value) = table.get("sync_numlock") {
+        config.sync_numlock = value
+            .as_bool()
+            .ok_or(invalid_config_field("sync_numlock"))?;
+    }
+    if let Some(value) = table.get("activation_key") {
+        config.activation_key = value
+            .as_str()
+            .and_then(|name| name.parse().ok())
+            .ok_or(invalid_config_field("activation_key"))?;
+    }
+    if let Some(value) = table.get("haptic_strength") {
+        let strength = as_float(value)
+            .filter(|strength| (0.0..=1.0).contains(strength))
+            .ok_or(invalid_config_field("haptic_strength"))?;
+        config.haptic_strength = Some(strength).filter(|strength| *strength > 0.0);
+    }
+    if let Some(value) = table.get("key_flash") {
+        config.key_flash = value.as_bool().ok_or(invalid_config_field("key_flash"))?;
+    }
+    if let Some(fade_duration) = get_duration(&table, "fade_ms", Duration::from_millis)? {
+        config.fade_duration = fade_duration;
+    }
+    if let Some(value) = table.get("decimal_key") {
+        config.decimal_key = value
+            .as_str()
+            .and_then(parse_ev_key)
+            .filter(|key| DECIMAL_KEYS.contains(key))
+            .ok_or(invalid_config_field("decimal_key"))?;
+    }
+    for (name, margin) in [
+        ("key_margin_x", &mut config.margins.x),
+        ("key_margin_y", &mut config.margins.y),
+    ] {
+        if let Some(value) = table.get(name) {
+            *margin = value
+                .as_integer()
+                .and_then(|margin| usize::try_from(margin).ok())
+                .filter(|margin| *margin > 0)
+                .ok_or(invalid_config_field(name))?;
+        }
+    }
+    for (name, id) in [
+        ("keyboard_bustype", &mut config.keyboard_id.bustype),
+        ("keyboard_vendor", &mut config.keyboard_id.vendor),
+        ("keyboard_product", &mut config.keyboard_id.product),
+        ("keyboard_version", &mut config.keyboard_id.version),
+    ] {
+        if let Some(value) = table.get(name) {
+            *id = value
+                .as_integer()
+                .and_then(|id| u16::try_from(id).ok())
+                .ok_or(invalid_config_field(name))?;
+        }
+    }
+    if let Some(value) = table.get("light_address") {
+        config.light_address = value
+            .as_integer()
+            .and_then(|address| u16::try_from(address).ok())
+            .filter(|address| is_valid_address(*address))
+            .ok_or(invalid_config_field("light_address"))?;
+    }
+    if let Some(value) = table.get("light_model") {
+        config.light_model = value
+            .as_str()
+            .and_then(LightModel::by_name)
+            .ok_or(invalid_config_field("light_model"))?;
+    }
+    if let Some(value) = table.get("light_retries") {
+        config.light_retries = value
+            .as_integer()
+            .and_then(|retries| u32::try_from(retries).ok())
+            .ok_or(invalid_config_field("light_retries"))?;
+    }
+    if let Some(value) = table.get("brightness_gamma") {
+        config.brightness_gamma = as_float(value)
+            .filter(|gamma| is_valid_gamma(*gamma))
+            .ok_or(invalid_config_field("brightness_gamma"))?;
+    }
+    if let Some(value) = table.get("activation_blinks") {
+        config.activation_blinks = value
+            .as_integer()
+            .and_then(|count| u32::try_from(count).ok())
+            .ok_or(invalid_config_field("activation_blinks"))?;
+    }
+    if let Some(blink_interval) = get_duration(&table, "blink_interval_ms", Duration::from_millis)?
+    {
+        config.blink_interval = blink_interval;
+    }
+    if let Some(breathing_period) =
+        get_duration(&table, "breathing_period_ms", Duration::from_millis)?
+    {
+        config.breathing_period = Some(breathing_period).filter(|period| !period.is_zero());
+    }
+    if let Some(value) = table.get("breathing_depth") {
+        config.breathing_depth = as_float(value)
+            .filter(|depth| (0.0..=1.0).contains(depth))
+            .ok_or(invalid_config_field("breathing_depth"))?;
+    }
+    if let Some(value) = table.get("two_finger_right_click") {
+        config.two_finger_right_click = value
+            .as_bool()
+            .ok_or(invalid_config_field("two_finger_right_click"))?;
+    }
+    if let Some(value) = table.get("mirror_layout") {
+        config.mirror_layout = value
+            .as_bool()
+            .ok_or(invalid_config_field("mirror_layout"))?;
+    }
+    if let Some(value) = table.get("light_always_on") {
+        config.light_always_on = value
+            .as_bool()
+            .ok_or(invalid_config_field("light_always_on"))?;
+    }
+    Ok(config)
+}
+
+impl Config {
+    /// Override the settings with the ones given in `NUMPAD_*` environment variables.
+    pub fn apply_env(&mut self) -> Result<(), ConfigError> {
+        if let Some(ms) = env_var("NUMPAD_HOLD_MS")? {
+            self.hold_duration = Duration::from_millis(ms);
+        }
+        if let Some(distance) = env_var::<f64>("NUMPAD_DRAG_DIST")? {
+            if distance < 0.0 {
+                return Err(ConfigError::InvalidEnvVar {
+                    name: "NUMPAD_DRAG_DIST",
+                    value: distance.to_string(),
+                });
+            }
+            self.drag_distance = distance;
+        }
+        if let Ok(name) = std::env::var("NUMPAD_TOUCHPAD_NAME") {
+            self.touchpad_name = name
+                .split(',')
+                .map(str::trim)
+                .filter(|part| !part.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+        Ok(())
+    }
+}
+
+fn invalid_config_field(field: &'static str) -> ConfigError {
+    ConfigError::InvalidField {
+        field,
+        location: "the config".to_string(),
+    }
+}
+
+/// Get a non negative integer field as a duration in the unit given by `from_int`.
+fn get_duration(
+    table: &Table,
+    field: &'static str,
+    from_int: fn(u64) -> Duration,
+) -> Result<Option<Duration>, ConfigError> {
+    table
+        .get(field)
+        .map(|value| {
+            value
+                .as_integer()
+                .and_then(|value| u64::try_from(value).ok())
+                .map(from_int)
+                .ok_or(invalid_config_field(field))
+        })
+        .transpose()
+}
+
+// toml distinguishes 30 from 30.0, but people shouldn't have to
+fn as_float(value: &Value) -> Option<f64> {
+    value
+        .as_float()
+        .or_else(|| value.as_integer().map(|value| value as f64))
+}
+
+fn env_var<T: std::str::FromStr>(name: &'static str) -> Result<Option<T>, ConfigError> {
+    match std::env::var(name) {
+        Ok(value) => value
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| ConfigError::InvalidEnvVar { name, value }),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Parse a `[[chords]]` entry, which needs two different `keys` and at least one key to `press`.
+fn parse_chord(value: &Value) -> Option<Chord> {
+    fn parse_keys(value: &Value) -> Option<Vec<EV_KEY>> {
+        value
+            .as_array()?
+            .iter()
+            .map(|key| key.as_str().and_then(parse_ev_key))
+            .collect()
+    }
+    let table = value.as_table()?;
+    let [first, second] = parse_keys(table.get("keys")?)?[..] else {
+        return None;
+    };
+    let press = parse_keys(table.get("press")?)?;
+    (first != second && !press.is_empty()).then_some(Chord {
+        keys: (first, second),
+        press,
+    })
+}
+
+/// The state which is kept across restarts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SavedState {
+    pub brightness: u8,
+    pub is_active: bool,
+}
+
+impl Default for SavedState {
+    fn default() -> Self {
+        Self {
+            brightness: MAX_BRIGHTNESS,
+            is_active: false,
+        }
+    }
+}
+
+impl SavedState {
+    /// Load the state saved at the given path. Returns None if there is no state or it is corrupt,
+    /// in which case the defaults should be used.
+    pub fn load(path: &Path) -> Option<Self> {
+        let table: Table = std::fs::read_to_string(path).ok()?.parse().ok()?;
+        let brightness = table
+            .get("brightness")?
+            .as_integer()
+            .and_then(|brightness| u8::try_from(brightness).ok())?;
+        let is_active = table.get("is_active")?.as_bool()?;
+        Some(Self {
+            brightness,
+            is_active,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut table = Table::new();
+        table.insert("brightness".to_string(), Value::from(self.brightness));
+        table.insert("is_active".to_string(), Value::from(self.is_active));
+        std::fs::write(path, table.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{key_simulation::ev_key_name, layout::default_numpad_layout};
+
+    const LAYOUT: &str = r#"
+        size = [1000, 500]
+
+        [[rows]]
+        min_y = 260
+        max_y = 500
+        items = [
+            { left_x = 0, right_x = 490, key = "KEY_KP1", hold_key = "KEY_END" },
+            { left_x = 510, right_x = 1000, key = "BRIGHTNESS_UP" },
+        ]
+
+        [[rows]]
+        min_y = 0
+        max_y = 240
+        items = [{ left_x = 0, right_x = 1000, key = "KEY_NUMLOCK" }]
+    "#;
+
+    #[test]
+    fn parse_layout_reads_a_layout_file() {
+        let layout = parse_layout(LAYOUT).unwrap();
+        assert_eq!(layout.size(), Some((1000, 500)));
+        assert_eq!(
+            layout.get_item(500, 100),
+            Some(&Action::Key(EV_KEY::KEY_NUMLOCK))
+        );
+        let item = layout.get_row_item(100, 300).unwrap();
+        assert_eq!(item.item(), &Action::Key(EV_KEY::KEY_KP1));
+        assert_eq!(item.hold_item(), Some(&Action::Key(EV_KEY::KEY_END)));
+        assert_eq!(layout.get_item(600, 300), Some(&Action::BrightnessUp));
+        // the margin between the items
+        assert_eq!(layout.get_item(500, 300), None);
+    }
+
+    #[test]
+    fn parse_layout_takes_every_action() {
+        for (name, action) in [
+            ("TOGGLE_ACTIVE", Action::ToggleActive),
+            ("BRIGHTNESS_UP", Action::BrightnessUp),
+            ("BRIGHTNESS_DOWN", Action::BrightnessDown),
+            ("MOD_LEFTCTRL", Action::Modifier(EV_KEY::KEY_LEFTCTRL)),
+            ("NEXT_LAYOUT", Action::NextLayout),
+            ("SWITCH_LAYOUT_0", Action::SwitchLayout(0)),
+            ("DEAD_ZONE", Action::DeadZone),
+            ("U+00B0", Action::Unicode('°')),
+            ("8", Action::Key(EV_KEY::KEY_8)),
+        ] {
+            let layout = parse_layout(&LAYOUT.replace("KEY_NUMLOCK", name)).unwrap();
+            assert_eq!(layout.get_item(500, 100), Some(&action), "{}", name);
+        }
+    }
+
+    #[test]
+    fn parse_layout_rejects_unknown_keys() {
+        let Err(ConfigError::UnknownKey { key, location }) =
+            parse_layout(&LAYOUT.replace("KEY_END", "KEY_NOPE"))
+        else {
+            panic!("an unknown key was accepted");
+        };
+        assert_eq!(key, "KEY_NOPE");
+        assert_eq!(location, "row 0, item 0");
+    }
+
+    #[test]
+    fn parse_layout_requires_the_row_bounds() {
+        for field in ["min_y", "max_y"] {
+            let text = LAYOUT.replace(&format!("{} = 260", field), "");
+            let text = text.replace(&format!("{} = 500", field), "");
+            let Err(ConfigError::InvalidField {
+                field: missing,
+                location,
+            }) = parse_layout(&text)
+            else {
+                panic!("a row without {} was accepted", field);
+            };
+            assert_eq!(missing, field);
+            assert_eq!(location, "row 0");
+        }
+    }
+
+    #[test]
+    fn parse_layout_rejects_inverted_bounds() {
+        let text = LAYOUT
+            .replace("max_y = 240", "max_y = 200")
+            .replace("min_y = 0", "min_y = 220");
+        assert!(matches!(
+            parse_layout(&text),
+            Err(ConfigError::InvalidLayout(LayoutError::InvertedRowBounds {
+                min_y: 220,
+                max_y: 200,
+                ..
+            }))
+        ));
+        let text = LAYOUT.replace(
+            "left_x = 510, right_x = 1000",
+            "left_x = 900, right_x = 510",
+        );
+        assert!(matches!(
+            parse_layout(&text),
+            Err(ConfigError::InvalidLayout(
+                LayoutError::InvertedItemBounds {
+                    left_x: 900,
+                    right_x: 510,
+                    ..
+                }
+            ))
+        ));
+    }
+
+    #[test]
+    fn layout_to_toml_round_trips() {
+        for layout in [
+            parse_layout(LAYOUT).unwrap(),
+            default_numpad_layout(),
+            default_numpad_layout().with_size(3900, 2600),
+        ] {
+            assert_eq!(parse_layout(&layout_to_toml(&layout)).unwrap(), layout);
+        }
+    }
+
+    #[test]
+    fn parse_config_reads_the_fields_and_rejects_invalid_ones() {
+        let config = parse_config("hold_ms = 400\ndrag_distance = 50").unwrap();
+        assert_eq!(config.hold_duration, Duration::from_millis(400));
+        assert_eq!(config.drag_distance, 50.0);
+        assert!(matches!(
+            parse_config("hold_ms = \"long\""),
+            Err(ConfigError::InvalidField {
+                field: "hold_ms",
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse_config("hold_ms ="),
+            Err(ConfigError::Toml(_))
+        ));
+    }
+
+    #[test]
+    fn decimal_key_is_one_of_the_separators() {
+        for key in DECIMAL_KEYS {
+            let config = parse_config(&format!("decimal_key = \"{}\"", ev_key_name(key))).unwrap();
+            assert_eq!(config.decimal_key, key);
+        }
+        for name in ["KEY_A", "KEY_KPDOT", "KEY_NOPE"] {
+            assert!(matches!(
+                parse_config(&format!("decimal_key = \"{}\"", name)),
+                Err(ConfigError::InvalidField {
+                    field: "decimal_key",
+                    ..
+                })
+            ));
+        }
+    }
+
+    #[test]
+    fn saved_state_round_trips() {
+        let path = std::env::temp_dir()
+            .join(format!("numpad_driver_test_{}", std::process::id()))
+            .join("state.toml");
+        let state = SavedState {
+            brightness: 3,
+            is_active: true,
+        };
+        state.save(&path).unwrap();
+        assert_eq!(SavedState::load(&path), Some(state));
+        std::fs::write(&path, "brightness = 300\nis_active = true").unwrap();
+        assert_eq!(SavedState::load(&path), None);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        assert_eq!(SavedState::load(&path), None);
+    }
+}
@@ -0,0 +1,287 @@
+use std::sync::{Arc, Mutex};
+
+use tracing::{info, warn};
+use zbus::{
+    MatchRule,
+    blocking::{Connection, MessageIterator, connection},
+    fdo, interface,
+    message::Type,
+    zvariant::OwnedObjectPath,
+};
+
+use crate::dev::{Command, Controller, NumberPad, Status};
+
+pub const BUS_NAME: &str = "org.numpad_driver.NumberPad";
+pub const OBJECT_PATH: &str = "/org/numpad_driver/NumberPad";
+
+/// Where logind's manager is, which knows the sessions.
+const LOGIN_BUS_NAME: &str = "org.freedesktop.login1";
+const LOGIN_MANAGER_PATH: &str = "/org/freedesktop/login1";
+/// The interface of logind's sessions, which signals when a session is locked and unlocked.
+const LOGIN_SESSION_INTERFACE: &str = "org.freedesktop.login1.Session";
+/// The interface of logind's manager, which signals when the system is about to sleep and woke up.
+const LOGIN_MANAGER_INTERFACE: &str = "org.freedesktop.login1.Manager";
+
+/// Which bus to serve the interface on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Bus {
+    Session,
+    /// Owning the name on the system bus needs a policy allowing it, see the README.
+    System,
+}
+
+struct NumberPadInterface {
+    controller: Controller,
+    // kept up to date by the thread spawned in serve, layouts and all
+    status: Arc<Mutex<Status>>,
+}
+
+impl NumberPadInterface {
+    fn send(&self, command: Command) -> fdo::Result<()> {
+        self.controller
+            .send(command)
+            .map_err(|error| fdo::Error::Failed(error.to_string()))
+    }
+}
+
+#[interface(name = "org.numpad_driver.NumberPad")]
+impl NumberPadInterface {
+    fn toggle(&self) -> fdo::Result<()> {
+        self.send(Command::Toggle)
+    }
+
+    fn set_active(&self, active: bool) -> fdo::Result<()> {
+        self.send(Command::SetActive(active))
+    }
+
+    fn set_brightness(&self, brightness: u8) -> fdo::Result<()> {
+        let max_brightness = self.status.lock().unwrap().max_brightness;
+        if brightness > max_brightness {
+            return Err(fdo::Error::InvalidArgs(format!(
+                "the brightness must be at most {}",
+                max_brightness
+            )));
+        }
+        self.send(Command::SetBrightness(brightness))
+    }
+
+    fn pause(&self) -> fdo::Result<()> {
+        self.send(Command::SetPaused(true))
+    }
+
+    fn resume(&self) -> fdo::Result<()> {
+        self.send(Command::SetPaused(false))
+    }
+
+    fn next_layout(&self) -> fdo::Result<()> {
+        self.send(Command::NextLayout)
+    }
+
+    fn set_layout(&self, name: String) -> fdo::Result<()> {
+        if !self.status.lock().unwrap().layout_names.contains(&name) {
+            return Err(fdo::Error::InvalidArgs(format!(
+                "there's no layout called {:?}",
+                name
+            )));
+        }
+        self.send(Command::SetLayout(name))
+    }
+
+    #[zbus(property)]
+    fn active(&self) -> bool {
+        self.status.lock().unwrap().is_active
+    }
+
+    #[zbus(property)]
+    fn paused(&self) -> bool {
+        self.status.lock().unwrap().is_paused
+    }
+
+    #[zbus(property)]
+    fn brightness(&self) -> u8 {
+        self.status.lock().unwrap().brightness
+    }
+
+    #[zbus(property)]
+    fn layout(&self) -> String {
+        let status = self.status.lock().unwrap();
+        // the names are sent along with the index, so this only misses if the status is inconsistent
+        status
+            .layout_names
+            .get(status.layout)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    #[zbus(property)]
+    fn layouts(&self) -> Vec<String> {
+        self.status.lock().unwrap().layout_names.clone()
+    }
+}
+
+/// Serve the `org.numpad_driver.NumberPad` interface for the given number pad on the given bus.
+/// The method calls are handled by [NumberPad::enter_input_loop]; the interface is served for as long
+/// as the returned connection is kept around.
+pub fn serve(number_pad: &mut NumberPad, bus: Bus) -> zbus::Result<Connection> {
+    let status = Arc::new(Mutex::new(number_pad.status()));
+    let statuses = number_pad.subscribe();
+    let interface = NumberPadInterface {
+        controller: number_pad.controller()?,
+        status: status.clone(),
+    };
+    let builder = match bus {
+        Bus::Session => connection::Builder::session()?,
+        Bus::System => connection::Builder::system()?,
+    };
+    let connection = builder
+        .name(BUS_NAME)?
+        .serve_at(OBJECT_PATH, interface)?
+        .build()?;
+    info!("serving {} on the {:?} bus", BUS_NAME, bus);
+
+    let interface = connection
+        .object_server()
+        .interface::<_, NumberPadInterface>(OBJECT_PATH)?;
+    // emit the property change signals; this stops once the number pad is dropped
+    std::thread::spawn(move || {
+        for new_status in statuses {
+            let old_status = std::mem::replace(&mut *status.lock().unwrap(), new_status.clone());
+            let emitter = interface.signal_emitter();
+            let interface = interface.get();
+            let result = if old_status.is_active != new_status.is_active {
+                zbus::block_on(interface.active_changed(emitter))
+            } else {
+                Ok(())
+            }
+            .and_then(|()| {
+                if old_status.is_paused != new_status.is_paused {
+                    zbus::block_on(interface.paused_changed(emitter))
+                } else {
+                    Ok(())
+                }
+            })
+            .and_then(|()| {
+                if old_status.brightness != new_status.brightness {
+                    zbus::block_on(interface.brightness_changed(emitter))
+                } else {
+                    Ok(())
+                }
+            })
+            .and_then(|()| {
+                if old_status.layout_names != new_status.layout_names {
+                    zbus::block_on(interface.layouts_changed(emitter))
+                } else {
+                    Ok(())
+                }
+            })
+            .and_then(|()| {
+                // the name changes if the layouts at the index do
+                if old_status.layout != new_status.layout
+                    || old_status.layout_names != new_status.layout_names
+                {
+                    zbus::block_on(interface.layout_changed(emitter))
+                } else {
+                    Ok(())
+                }
+            });
+            if let Err(error) = result {
+                warn!("couldn't emit a property change on D-Bus: {}", error);
+            }
+        }
+    });
+    Ok(connection)
+}
+
+/// The object path of the logind session we're running in: the one `XDG_SESSION_ID` names if it's set,
+/// otherwise the one logind put this process in.
+fn own_session(connection: &Connection) -> zbus::Result<OwnedObjectPath> {
+    let reply = match std::env::var("XDG_SESSION_ID") {
+        Ok(id) if !id.is_empty() => connection.call_method(
+            Some(LOGIN_BUS_NAME),
+            LOGIN_MANAGER_PATH,
+            Some(LOGIN_MANAGER_INTERFACE),
+            "GetSession",
+            &id,
+        )?,
+        _ => connection.call_method(
+            Some(LOGIN_BUS_NAME),
+            LOGIN_MANAGER_PATH,
+            Some(LOGIN_MANAGER_INTERFACE),
+            "GetSessionByPID",
+            &std::process::id(),
+        )?,
+    };
+    reply.body().deserialize()
+}
+
+/// Pause the number pad while our session is locked and resume it once it's unlocked, as logind signals
+/// it on the system bus, so that nothing is typed by accident and the light is off while the user is
+/// away. Other users' sessions locking doesn't matter, unless we aren't running in a session at all,
+/// e.g. as a system service, in which case any session locking pauses it.
+/// Returns the connection, which has to be kept around for as long as this should happen.
+pub fn pause_while_locked(number_pad: &mut NumberPad) -> zbus::Result<Connection> {
+    let controller = number_pad.controller()?;
+    let connection = Connection::system()?;
+    let session = own_session(&connection)
+        .inspect_err(|error| {
+            warn!(
+                "couldn't find the session we're running in, pausing when any session is locked: {}",
+                error
+            )
+        })
+        .ok();
+    let mut rule = MatchRule::builder()
+        .msg_type(Type::Signal)
+        .interface(LOGIN_SESSION_INTERFACE)?;
+    if let Some(session) = &session {
+        rule = rule.path(session.as_ref())?;
+    }
+    let messages = MessageIterator::for_match_rule(rule.build(), &connection, None)?;
+    match &session {
+        Some(session) => info!(%session, "pausing the numpad while the session is locked"),
+        None => info!("pausing the numpad while a session is locked"),
+    }
+    // this stops once the number pad is dropped
+    std::thread::spawn(move || {
+        for message in messages.flatten() {
+            let header = message.header();
+            let command = match header.member().map(|member| member.as_str()) {
+                Some("Lock") => Command::SetPaused(true),
+                Some("Unlock") => Command::SetPaused(false),
+                _ => continue,
+            };
+            if controller.send(command).is_err() {
+                break;
+            }
+        }
+    });
+    Ok(connection)
+}
+
+/// Restore the number pad whenever the system wakes up from a suspend, as logind signals it on the system
+/// bus with `PrepareForSleep(false)`; see [NumberPad::restore_after_sleep]. Returns the connection,
+/// which has to be kept around for as long as this should happen.
+pub fn restore_after_sleep(number_pad: &mut NumberPad) -> zbus::Result<Connection> {
+    let controller = number_pad.controller()?;
+    let connection = Connection::system()?;
+    let rule = MatchRule::builder()
+        .msg_type(Type::Signal)
+        .interface(LOGIN_MANAGER_INTERFACE)?
+        .member("PrepareForSleep")?
+        .build();
+    let messages = MessageIterator::for_match_rule(rule, &connection, None)?;
+    info!("restoring the numpad after suspends");
+    // this stops once the number pad is dropped
+    std::thread::spawn(move || {
+        for message in messages.flatten() {
+            // true is sent before going to sleep, false after waking up
+            if message.body().deserialize::<bool>() != Ok(false) {
+                continue;
+            }
+            if controller.send(Command::RestoreAfterSleep).is_err() {
+                break;
+            }
+        }
+    });
+    Ok(connection)
+}
@@ -1,126 +1,501 @@
 use std::{
-    io::ErrorKind,
-    os::fd::AsRawFd,
+    io::{ErrorKind, Read, Write},
+    os::{fd::AsRawFd, unix::net::UnixStream},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, Sender},
+    thread::JoinHandle,
     time::{Duration, Instant},
 };
 
 use evdev_rs::{
-    Device, GrabMode, InputEvent, ReadFlag,
-    enums::{EV_ABS, EV_KEY, EV_MSC, EventCode},
+    Device, DeviceWrapper, GrabMode, InputEvent, ReadFlag, ReadStatus,
+    enums::{EV_ABS, EV_KEY, EV_SYN, EventCode},
 };
-use libc::{POLLIN, pollfd};
+use libc::{ENODEV, POLLERR, POLLHUP, POLLIN, POLLNVAL, pollfd};
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
 use thiserror::Error;
+use tracing::{debug, info, warn};
 
+#[cfg(feature = "notify")]
+use crate::notification::BrightnessNotification;
 use crate::{
-    key_simulation::KeySimulator,
-    layout::{Layout, default_numpad_layout},
-    numpad_light::{MAX_BRIGHTNESS, NumpadLight},
+    config::{self, Config, ConfigError, SavedState},
+    discovery::{self, TouchPadId},
+    haptics::Haptics,
+    key_simulation::{self, DEVICE_NAME, KeySimulator, ev_key_name},
+    layout::{Action, Layout, Rect, default_numpad_layout_with_margins},
+    numpad_light::{self, LightStatus, LightTransport, MAX_BRIGHTNESS, NumpadLight},
+    output::{GrabControl, KeyOutput, LightOutput},
 };
 
-// TODO:
-// Currently, when the numpad is enabled, double touch does not work, so in order to scroll you need
-// to use 1 finger and then the other one. Fix.
-// We should probably use the ID given to us by evdev
-
-struct TouchPadId {
-    i2c_id: u32,
-    ev_id: u32,
-}
-fn get_touchpad_id() -> std::io::Result<TouchPadId> {
-    let devices = std::fs::read_to_string("/proc/bus/input/devices")?;
-    let mut i2c_id: u32 = 0;
-    let mut ev_id: u32 = 0;
-    let mut is_in_touchpad_block = false;
-    for line in devices.lines() {
-        if is_in_touchpad_block {
-            if line.starts_with("S:") {
-                i2c_id = line
-                    .split("i2c-")
-                    .nth(1)
-                    .unwrap()
-                    .chars()
-                    .take_while(|c| c.is_numeric())
-                    .collect::<String>()
-                    .parse()
-                    .unwrap();
-            } else if line.starts_with("H:") {
-                ev_id = line
-                    .split("event")
-                    .nth(1)
-                    .unwrap()
-                    .chars()
-                    .take_while(|c| c.is_numeric())
-                    .collect::<String>()
-                    .parse()
-                    .unwrap();
-                // H appears after the S, so we're done parsing
-                break;
-            }
-        } else {
-            is_in_touchpad_block =
-                line.starts_with("N:") && line.contains("ASUF") && line.contains("Touchpad");
+mod gesture;
+
+use gesture::{NumpadState, Phase};
+
+/// The range of values the touchpad reports for some axis.
+#[derive(Debug, Clone, Copy)]
+struct AxisRange {
+    min: i32,
+    max: i32,
+}
+
+impl AxisRange {
+    fn of(touchpad: &Device, axis: EV_ABS) -> Option<Self> {
+        let info = touchpad.abs_info(&EventCode::EV_ABS(axis))?;
+        if info.maximum <= info.minimum {
+            return None;
         }
+        Some(Self {
+            min: info.minimum,
+            max: info.maximum,
+        })
+    }
+
+    /// Map a value of this axis into 0.0..=1.0
+    fn normalize(&self, value: usize) -> f64 {
+        ((value as f64 - self.min as f64) / (self.max - self.min) as f64).clamp(0.0, 1.0)
     }
-    if i2c_id == 0 {
-        return Err(std::io::Error::new(
-            ErrorKind::Other,
-            "could not find touchpad i2c ID!",
-        ));
-    } else if ev_id == 0 {
-        return Err(std::io::Error::new(
-            ErrorKind::Other,
-            "could not find touchpad ev ID!",
-        ));
+}
+
+/// Mirror the layout horizontally within the touchpad, or within its own size if it has one.
+fn mirror_layout(layout: Layout<Action>, axes: Option<(AxisRange, AxisRange)>) -> Layout<Action> {
+    match (layout.size(), axes) {
+        (Some((width, _)), _) => layout.mirror_x(width),
+        (None, Some((x_range, _))) => layout.mirror_x((x_range.min + x_range.max).max(0) as usize),
+        (None, None) => {
+            warn!("the touchpad didn't report its width, so the layout can't be mirrored");
+            layout
+        }
     }
+}
 
-    Ok(TouchPadId { i2c_id, ev_id })
+/// The light turning off and on again a number of times, see [NumberPad::start_blinking].
+#[derive(Debug, Clone, Copy)]
+struct Blink {
+    /// How many more times the light is turned off or on; the light is on while it's even.
+    toggles_left: u32,
+    next_toggle: Instant,
 }
 
-#[derive(Debug)]
-struct LastTouch {
-    pos_x: usize,
-    pos_y: usize,
-    time: Instant,
-    key: Option<EV_KEY>,
+/// The light fading down and up again while the numpad is active, see [Config::breathing_period].
+#[derive(Debug, Clone, Copy)]
+struct Breath {
+    start: Instant,
+    next_update: Instant,
+    /// The level the light was last set to.
+    level: u8,
 }
-#[derive(Debug)]
-pub struct NumpadState {
-    pos_x: usize,
-    pos_y: usize,
-    last_touch: LastTouch,
-    is_active: bool,
-    is_dragging: bool,
-    is_lifted: bool,
+
+/// How a sticky modifier is pressed along with keys, see [Action::Modifier].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ModifierState {
+    /// Pressed along with the next key only.
+    Armed,
+    /// Pressed along with every key.
+    Locked,
 }
 
-impl NumpadState {
-    fn new() -> Self {
+#[derive(Debug, Clone)]
+enum LayoutSource {
+    Layout(Layout<Action>),
+    Path(PathBuf),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum InitialBrightness {
+    Level(u8),
+    Percent(u8),
+}
+
+/// Configures a [NumberPad] before creating it. Anything which isn't set comes from the config file
+/// and the environment, see [Config], or from the state saved by the last run.
+#[derive(Debug, Clone)]
+pub struct NumberPadBuilder {
+    layout: Option<LayoutSource>,
+    brightness: Option<InitialBrightness>,
+    brightness_gamma: Option<f64>,
+    hold_duration: Option<Duration>,
+    drag_distance: Option<f64>,
+    light_enabled: bool,
+    activation_key: Option<Action>,
+    light_always_on: Option<bool>,
+    invert_brightness_drag: Option<bool>,
+    passthrough: bool,
+    dry_run: bool,
+    trace_raw: bool,
+}
+
+impl Default for NumberPadBuilder {
+    fn default() -> Self {
         Self {
-            pos_x: 0,
-            pos_y: 0,
-            last_touch: LastTouch {
-                pos_x: 0,
-                pos_y: 0,
-                time: Instant::now(),
-                key: None,
-            },
-            is_active: false,
-            is_dragging: false,
-            is_lifted: true,
+            layout: None,
+            brightness: None,
+            brightness_gamma: None,
+            hold_duration: None,
+            drag_distance: None,
+            light_enabled: true,
+            activation_key: None,
+            light_always_on: None,
+            invert_brightness_drag: None,
+            passthrough: false,
+            dry_run: false,
+            trace_raw: false,
         }
     }
 }
 
-pub struct NumberPad {
-    touchpad: Device,
-    key_simulator: KeySimulator,
-    light_controller: NumpadLight,
+/// Turns the touchpad into a numpad. The touchpad, the keys and the light are generic so that they
+/// can be replaced, e.g. by fakes for driving the logic with scripted events; see [NumberPad::from_parts].
+pub struct NumberPad<T = Device, K = KeySimulator, L = NumpadLight>
+where
+    T: GrabControl,
+    K: KeyOutput,
+    L: LightOutput,
+{
+    touchpad: T,
+    key_simulator: K,
+    /// None if we were asked not to control the light.
+    light_controller: Option<L>,
     state: NumpadState,
-    layout: Layout<EV_KEY>,
-    holding_key: Option<EV_KEY>,
+    /// The layouts which can be switched between and their names, starting with the main one.
+    layouts: Vec<(String, Layout<Action>)>,
+    /// The index of the layout in use.
+    layout_index: usize,
+    /// The touchpad's x and y ranges, used for layouts which aren't in raw device coordinates.
+    axes: Option<(AxisRange, AxisRange)>,
+    /// The touchpad's range of contact sizes, or None if it doesn't report them.
+    touch_major_range: Option<AxisRange>,
+    /// The modifiers which were pressed along with the key held in [Phase::Holding].
+    held_modifiers: Vec<EV_KEY>,
+    /// The sticky modifiers which get pressed along with the next key, in the order they were tapped.
+    modifiers: Vec<(EV_KEY, ModifierState)>,
+    /// The last key which was tapped and when, for ignoring bounces.
+    last_press: Option<(EV_KEY, Instant)>,
+    /// When the held key should repeat next.
+    next_repeat: Option<Instant>,
+    /// When the light should be restored to the actual brightness after flashing for a key press.
+    flash_end: Option<Instant>,
+    /// The blinking in progress after the numpad was activated, if any.
+    blink: Option<Blink>,
+    /// When to try restoring the light again after it failed right after a suspend, and how many
+    /// attempts were made so far; see [NumberPad::restore_after_sleep].
+    light_restore: Option<(Instant, u32)>,
     brightness: u8,
+    /// The settings it runs with; the ones which can't change while it's running, e.g.
+    /// [Config::kernel_repeat], are kept as they were when it was created, see [NumberPad::reload].
+    config: Config,
+    /// How many levels the current drag from the activation key changed the brightness by so far,
+    /// see [Config::live_brightness_drag].
+    brightness_drag_levels: i32,
+    /// When the activation key was last tapped without toggling, while it needs to be tapped twice.
+    last_activation_tap: Option<Instant>,
+    /// The breathing in progress, if any.
+    breath: Option<Breath>,
+    /// Whether the current touch is on a key but the touchpad wasn't grabbed for it yet, see
+    /// [Config::hybrid_grab_delay].
+    is_grab_deferred: bool,
+    /// None if we weren't asked to vibrate on key presses or the touchpad can't.
+    haptics: Option<Haptics>,
+    /// Created on the first call to [NumberPad::controller].
+    commands: Option<CommandChannel>,
+    status_senders: Vec<Sender<Status>>,
+    /// Where to save the state whenever it changes, if anywhere.
+    state_path: Option<PathBuf>,
+    /// The stats so far, except for the time since the numpad was last activated if it's active.
+    stats: Stats,
+    /// When the numpad was last activated, if it's active.
+    active_since: Option<Instant>,
+    /// See [NumberPadBuilder::passthrough].
+    passthrough: bool,
+    /// See [NumberPadBuilder::dry_run].
+    dry_run: bool,
+    /// See [NumberPad::set_trace_raw].
+    trace_raw: bool,
+    /// See [NumberPad::pause].
+    is_paused: bool,
+    /// Whether the input loop stops on SIGTERM and SIGINT and reloads on SIGHUP; it doesn't when it runs
+    /// on its own thread, since the signals are the host application's business then.
+    handles_signals: bool,
+    /// Whether a [Command::Shutdown] asked the input loop to stop.
+    is_shutting_down: bool,
+    /// Shown when the brightness is changed on the numpad.
+    #[cfg(feature = "notify")]
+    notification: BrightnessNotification,
+    /// The keys the key simulator can send, i.e. the ones the layouts and the config used when it was
+    /// created; build fails if the virtual keyboard couldn't enable all of them.
+    keys: Vec<EV_KEY>,
+    /// What the number pad was built with, for loading the config again, see [NumberPad::reload].
+    builder: Option<NumberPadBuilder>,
+}
+
+/// Something other than the touchpad asking the number pad to do something, see [NumberPad::controller].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Activate the numpad if it's inactive and vice versa, as touching the numlock key does.
+    Toggle,
+    SetActive(bool),
+    /// Set the brightness, which is clamped to [NumberPad::max_brightness].
+    SetBrightness(u8),
+    /// [NumberPad::pause] or [NumberPad::resume] the number pad.
+    SetPaused(bool),
+    /// Switch to the next layout, see [NumberPad::add_layout].
+    NextLayout,
+    /// Switch to the layout with the given name; unknown names are ignored.
+    SetLayout(String),
+    /// Press a key as tapping it would, see [NumberPad::press_key].
+    PressKey(EV_KEY),
+    /// The system woke up from a suspend, see [NumberPad::restore_after_sleep].
+    RestoreAfterSleep,
+    /// Stop the input loop, as SIGTERM does.
+    Shutdown,
+}
+
+/// What the number pad is currently doing, see [NumberPad::subscribe].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Status {
+    pub is_active: bool,
+    /// See [NumberPad::pause].
+    pub is_paused: bool,
+    pub brightness: u8,
+    /// See [NumberPad::max_brightness].
+    pub max_brightness: u8,
+    /// The index of the current layout in `layout_names`.
+    pub layout: usize,
+    /// See [NumberPad::layout_names]; they change when a layout is added or the config is reloaded.
+    pub layout_names: Vec<String>,
+}
+
+/// How the number pad has been used since it was created, see [NumberPad::stats].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// How many times each key was pressed, by tapping or holding it, in the order they were first pressed.
+    /// Keys which were never pressed aren't listed; if one is in the layout, it may be out of reach.
+    pub key_presses: Vec<(EV_KEY, u64)>,
+    /// How many times the numpad was activated or deactivated.
+    pub toggles: u64,
+    /// How long the numpad has been active in total.
+    pub active_time: Duration,
+}
+
+struct CommandChannel {
+    sender: Sender<Command>,
+    receiver: Receiver<Command>,
+    // a byte is written into this socket for every command so that it wakes up the event loop
+    wake_sender: UnixStream,
+    wake_receiver: UnixStream,
+}
+
+/// Sends commands to a [NumberPad], which handles them in its event loop; see [NumberPad::controller].
+#[derive(Debug)]
+pub struct Controller {
+    sender: Sender<Command>,
+    waker: UnixStream,
+}
+
+impl Controller {
+    /// Returns an error if the number pad is gone.
+    pub fn send(&self, command: Command) -> std::io::Result<()> {
+        self.sender
+            .send(command)
+            .map_err(|_| std::io::Error::from(ErrorKind::BrokenPipe))?;
+        match (&self.waker).write(&[0]) {
+            // if the socket is full, the event loop has plenty of reasons to wake up already
+            Err(error) if error.kind() != ErrorKind::WouldBlock => Err(error),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A number pad running on its own thread, see [NumberPad::spawn]. Dropping the handle leaves it running.
+#[derive(Debug)]
+pub struct NumpadHandle {
+    controller: Controller,
+    thread: JoinHandle<std::io::Result<()>>,
+}
+
+impl NumpadHandle {
+    pub fn toggle(&self) -> std::io::Result<()> {
+        self.controller.send(Command::Toggle)
+    }
+
+    /// See [Command::SetBrightness].
+    pub fn set_brightness(&self, brightness: u8) -> std::io::Result<()> {
+        self.controller.send(Command::SetBrightness(brightness))
+    }
+
+    /// For sending any other command.
+    pub fn controller(&self) -> &Controller {
+        &self.controller
+    }
+
+    /// Stop the input loop, which releases the touchpad and the light, and wait for the thread to end.
+    /// Returns the error the loop stopped with, if any.
+    pub fn shutdown(self) -> std::io::Result<()> {
+        // if the loop is gone already, joining tells why
+        let _ = self.controller.send(Command::Shutdown);
+        self.thread
+            .join()
+            .map_err(|_| std::io::Error::other("the input loop panicked"))?
+    }
+}
+
+/// Find the touchpad whose name contains all of `touchpad_name` and open it.
+fn open_touchpad(touchpad_name: &[String]) -> Result<(Device, TouchPadId), Error> {
+    let ids = discovery::find_touchpad(|name| {
+        touchpad_name
+            .iter()
+            .all(|part| name.contains(part.as_str()))
+    })
+    .map_err(Error::TouchpadNotFound)?;
+    let device_path = format!("/dev/input/event{}", ids.ev_id);
+    let touchpad =
+        Device::new_from_path(&device_path).map_err(|e| Error::CouldntOpenTouchpaddDevice {
+            device_name: device_path.to_string(),
+            error: e,
+        })?;
+    // the event number may have gone to our own keyboard since it was looked up
+    if touchpad.name() == Some(DEVICE_NAME) {
+        return Err(Error::TouchpadNotFound(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("{} is the numpad's own keyboard", device_path),
+        )));
+    }
+    info!(
+        "found the touchpad at {} with the light on i2c bus {}",
+        device_path, ids.i2c_id
+    );
+    Ok((touchpad, ids))
+}
+
+fn load_layout(path: PathBuf) -> Result<Layout<Action>, Error> {
+    info!("using the layout from {}", path.display());
+    config::load_layout(&path).map_err(|error| Error::CouldntLoadLayout { path, error })
+}
+
+/// Load the layouts from the layouts directory, named after their files, sorted by name.
+fn load_extra_layouts() -> Result<Vec<(String, Layout<Action>)>, Error> {
+    // the other layouts are optional
+    let mut layouts = Vec::new();
+    if let Some(Ok(entries)) = config::layouts_dir().map(std::fs::read_dir) {
+        let mut paths: Vec<(String, PathBuf)> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter_map(|path| Some((layout_name(&path)?, path)))
+            .collect();
+        paths.sort();
+        for (name, path) in paths {
+            layouts.push((name, load_layout(path)?));
+        }
+    }
+    Ok(layouts)
+}
+
+/// The name of the layout in the file, i.e. its name without the extension, or None if it isn't a
+/// layout file.
+fn layout_name(path: &Path) -> Option<String> {
+    if path.extension()? != "toml" {
+        return None;
+    }
+    Some(path.file_stem()?.to_string_lossy().into_owned())
+}
+
+/// The keys which need to be enabled for sending everything the layouts and the config can send.
+fn keys_to_enable<'a>(
+    layouts: impl IntoIterator<Item = &'a Layout<Action>>,
+    config: &Config,
+) -> Vec<EV_KEY> {
+    let mut keys: Vec<EV_KEY> = Vec::new();
+    let layout_keys = layouts
+        .into_iter()
+        .flat_map(Layout::items)
+        .flat_map(|action| match action {
+            Action::Key(key) | Action::Modifier(key) => vec![*key],
+            Action::Unicode(_) => config.unicode_input.keys(),
+            _ => Vec::new(),
+        });
+    let numlock_key = config.sync_numlock.then_some(EV_KEY::KEY_NUMLOCK);
+    // the buttons are only enabled when they're used, so that the device doesn't look like a mouse otherwise
+    let right_click = config.two_finger_right_click.then_some(EV_KEY::BTN_RIGHT);
+    let chord_keys = config
+        .chords
+        .iter()
+        .flat_map(|chord| chord.press.iter().copied());
+    for key in layout_keys
+        .chain(numlock_key)
+        .chain(right_click)
+        .chain(chord_keys)
+    {
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+    keys
+}
+
+/// The keys out of `keys` which aren't `enabled`, in order.
+fn missing_keys(keys: &[EV_KEY], enabled: &[EV_KEY]) -> Vec<EV_KEY> {
+    keys.iter()
+        .filter(|key| !enabled.contains(key))
+        .copied()
+        .collect()
+}
+
+/// Connect to the light on the I2C bus as the config says.
+fn open_light(i2c_id: u32, config: &Config) -> Result<NumpadLight, Error> {
+    let address = config.light_address;
+    let connection_error = |error| Error::CouldntConnectToNumpadLight {
+        i2c_id,
+        address,
+        error,
+    };
+    let mut light =
+        NumpadLight::new(i2c_id, address, config.light_model).map_err(connection_error)?;
+    light.set_retries(config.light_retries);
+    // the config only has valid ones, so this can't fail
+    light
+        .set_gamma(config.brightness_gamma)
+        .map_err(connection_error)?;
+    Ok(light)
+}
+
+/// The brightness the light is at, so that the numpad starts where it is rather than where it was when
+/// the state was last saved, which it may have been changed from since. Falls back to `saved` if the
+/// light can't report it.
+fn current_brightness<D: LightTransport>(light: &mut NumpadLight<D>, saved: u8) -> u8 {
+    match light.read_brightness() {
+        Ok(brightness) => brightness,
+        Err(i2cdev::linux::LinuxI2CError::Io(error))
+            if error.kind() == std::io::ErrorKind::Unsupported =>
+        {
+            debug!(%error, "using the saved brightness");
+            saved
+        }
+        Err(error) => {
+            warn!(%error, "couldn't read the brightness, using the saved one");
+            saved
+        }
+    }
 }
 
+/// How long to wait before the first attempt at finding the touchpad again after it disappeared,
+/// doubling after every failed attempt up to [MAX_RECONNECT_DELAY].
+const MIN_RECONNECT_DELAY: Duration = Duration::from_millis(500);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// How long to wait before trying to restore the light again when it isn't back yet after a suspend,
+/// doubling after every failed attempt, and how many attempts to make at most.
+const LIGHT_RESTORE_DELAY: Duration = Duration::from_millis(200);
+const LIGHT_RESTORE_ATTEMPTS: u32 = 6;
+
+/// The name of the layout given to [NumberPad::from_parts], or loaded from layout.toml.
+pub const MAIN_LAYOUT_NAME: &str = "main";
+
+/// How long the light stays dimmed when flashing for a key press.
+const FLASH_DURATION: Duration = Duration::from_millis(80);
+
+/// How often the level of the breathing light is updated; it's only written when it changes.
+const BREATHING_STEP: Duration = Duration::from_millis(50);
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("Could not find touchpad id; error: {}", .0)]
@@ -130,213 +505,2243 @@ pub enum Error {
         device_name: String,
         error: std::io::Error,
     },
-    #[error("Couldn't connect to the numpad's light: {}", .0)]
-    CouldntConnectToNumpadLight(i2cdev::linux::LinuxI2CError),
+    #[error("Couldn't connect to the numpad's light at address {:#04x} on /dev/i2c-{}: {}", .address, .i2c_id, .error)]
+    CouldntConnectToNumpadLight {
+        i2c_id: u32,
+        address: u16,
+        error: i2cdev::linux::LinuxI2CError,
+    },
     #[error("Couldn't create keyboard device: {}", .0)]
     CouldntCreateKeyboardDevice(std::io::Error),
+    #[error("Couldn't load layout from {}: {}", .path.display(), .error)]
+    CouldntLoadLayout { path: PathBuf, error: ConfigError },
+    #[error("Couldn't load config from {}: {}", .path.display(), .error)]
+    CouldntLoadConfig { path: PathBuf, error: ConfigError },
+    #[error("Invalid environment: {}", .0)]
+    InvalidEnvironment(ConfigError),
+    #[error("Couldn't control the numpad's light: {}", .0)]
+    CouldntControlLight(i2cdev::linux::LinuxI2CError),
+    #[error("Couldn't grab or ungrab the touchpad: {}", .0)]
+    CouldntGrabTouchpad(std::io::Error),
+    #[error("Couldn't emit key events: {}", .0)]
+    CouldntEmitKeys(std::io::Error),
+    #[error("Couldn't press {:?}, the virtual keyboard wasn't set up to send it", .0)]
+    CouldntPressKey(EV_KEY),
+    #[error("Couldn't enable {:?} on the virtual keyboard, though the layouts or the config use them", .0)]
+    CouldntEnableKeys(Vec<EV_KEY>),
+    #[error("Invalid brightness gamma {}, it has to be positive", .0)]
+    InvalidGamma(f64),
+}
+
+impl NumberPadBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use this layout rather than the one from the layout file.
+    pub fn layout(mut self, layout: impl Into<Layout<Action>>) -> Self {
+        self.layout = Some(LayoutSource::Layout(layout.into()));
+        self
+    }
+
+    /// Load the layout from this file rather than from the default location; unlike the default
+    /// layout file, this one has to exist.
+    pub fn layout_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.layout = Some(LayoutSource::Path(path.into()));
+        self
+    }
+
+    /// Start with this brightness rather than the light's current one (or the last used one if it can't
+    /// tell, see [NumpadLight::read_brightness]); it is clamped to the light's highest level.
+    pub fn initial_brightness(mut self, brightness: u8) -> Self {
+        self.brightness = Some(InitialBrightness::Level(brightness));
+        self
+    }
+
+    /// Like [NumberPadBuilder::initial_brightness], but as a percentage, which is mapped to the light's
+    /// levels with [Config::brightness_gamma]; see [numpad_light::percent_to_level].
+    pub fn initial_brightness_percent(mut self, percent: u8) -> Self {
+        self.brightness = Some(InitialBrightness::Percent(percent));
+        self
+    }
+
+    /// See [Config::brightness_gamma]; building fails if it isn't valid, see
+    /// [numpad_light::is_valid_gamma].
+    pub fn brightness_gamma(mut self, gamma: f64) -> Self {
+        self.brightness_gamma = Some(gamma);
+        self
+    }
+
+    /// See [NumberPad::set_hold_duration].
+    pub fn hold_duration(mut self, hold_duration: Duration) -> Self {
+        self.hold_duration = Some(hold_duration);
+        self
+    }
+
+    /// See [NumberPad::set_drag_distance].
+    pub fn drag_distance(mut self, drag_distance: f64) -> Self {
+        self.drag_distance = Some(drag_distance);
+        self
+    }
+
+    /// Whether to control the numpad's light at all; it is by default.
+    pub fn light_enabled(mut self, light_enabled: bool) -> Self {
+        self.light_enabled = light_enabled;
+        self
+    }
+
+    /// See [NumberPad::set_light_always_on].
+    pub fn light_always_on(mut self, light_always_on: bool) -> Self {
+        self.light_always_on = Some(light_always_on);
+        self
+    }
+
+    /// See [NumberPad::set_invert_brightness_drag].
+    pub fn invert_brightness_drag(mut self, invert_brightness_drag: bool) -> Self {
+        self.invert_brightness_drag = Some(invert_brightness_drag);
+        self
+    }
+
+    /// In passthrough mode the touches are handled as usual, including the light, but the touchpad is
+    /// never grabbed and no keys are sent; together with the logs, that's a safe way to try out a layout.
+    pub fn passthrough(mut self, passthrough: bool) -> Self {
+        self.passthrough = passthrough;
+        self
+    }
+
+    /// In a dry run the touches are handled as usual, but nothing is done about them: the touchpad
+    /// isn't grabbed, no keys are sent, the light isn't touched and the state isn't saved. Instead,
+    /// where each touch was lifted and the item it resolved to is printed, along with what would have
+    /// been grabbed and pressed, e.g. for telling whether a tap landed in the margin.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// See [NumberPad::set_trace_raw].
+    pub fn trace_raw(mut self, trace_raw: bool) -> Self {
+        self.trace_raw = trace_raw;
+        self
+    }
+
+    /// See [NumberPad::set_activation_key].
+    pub fn activation_key(mut self, activation_key: impl Into<Action>) -> Self {
+        self.activation_key = Some(activation_key.into());
+        self
+    }
+
+    /// The settings, from the config file and the environment, overridden by the builder's.
+    fn load_config(&self) -> std::result::Result<Config, Error> {
+        let mut config = match config::config_path() {
+            Some(path) if path.exists() => config::load_config(&path)
+                .map_err(|error| Error::CouldntLoadConfig { path, error })?,
+            _ => Config::default(),
+        };
+        config.apply_env().map_err(Error::InvalidEnvironment)?;
+        if let Some(hold_duration) = self.hold_duration {
+            config.hold_duration = hold_duration;
+        }
+        if let Some(drag_distance) = self.drag_distance {
+            config.drag_distance = drag_distance;
+        }
+        if let Some(activation_key) = self.activation_key {
+            config.activation_key = activation_key;
+        }
+        if let Some(light_always_on) = self.light_always_on {
+            config.light_always_on = light_always_on;
+        }
+        if let Some(invert_brightness_drag) = self.invert_brightness_drag {
+            config.invert_brightness_drag = invert_brightness_drag;
+        }
+        if let Some(gamma) = self.brightness_gamma {
+            if !numpad_light::is_valid_gamma(gamma) {
+                return Err(Error::InvalidGamma(gamma));
+            }
+            config.brightness_gamma = gamma;
+        }
+        Ok(config)
+    }
+
+    fn load_main_layout(&self, config: &Config) -> std::result::Result<Layout<Action>, Error> {
+        let custom_layout =
+            match &self.layout {
+                Some(LayoutSource::Layout(layout)) => layout.clone(),
+                // an explicitly given layout file has to exist, the default one is optional
+                Some(LayoutSource::Path(path)) => load_layout(path.clone())?,
+                // use the user's layout if they have one, otherwise fall back to the builtin one
+                None => match config::layout_path() {
+                    Some(path) if path.exists() => load_layout(path)?,
+                    _ => {
+                        return Ok(default_numpad_layout_with_margins(config.margins).map(
+                            |action| match action {
+                                Action::Key(EV_KEY::KEY_DOT) => Action::Key(config.decimal_key),
+                                action => action,
+                            },
+                        ));
+                    }
+                },
+            };
+        if config.decimal_key != EV_KEY::KEY_DOT {
+            warn!(
+                decimal_key = ev_key_name(config.decimal_key),
+                "decimal_key only changes the builtin layout, not the custom one"
+            );
+        }
+        Ok(custom_layout)
+    }
+
+    /// The main layout the number pad would be built with, without opening any devices.
+    pub fn main_layout(&self) -> std::result::Result<Layout<Action>, Error> {
+        self.load_main_layout(&self.load_config()?)
+    }
+
+    pub fn build(self) -> std::result::Result<NumberPad, Error> {
+        let config = self.load_config()?;
+        let mut layout = self.load_main_layout(&config)?;
+        let mut layouts = load_extra_layouts()?;
+        let (touchpad, ids) = open_touchpad(&config.touchpad_name)?;
+        let axes = AxisRange::of(&touchpad, EV_ABS::ABS_MT_POSITION_X)
+            .zip(AxisRange::of(&touchpad, EV_ABS::ABS_MT_POSITION_Y));
+        if config.mirror_layout {
+            layout = mirror_layout(layout, axes);
+            layouts = layouts
+                .into_iter()
+                .map(|(name, layout)| (name, mirror_layout(layout, axes)))
+                .collect();
+        }
+        let touch_major_range = AxisRange::of(&touchpad, EV_ABS::ABS_MT_TOUCH_MAJOR);
+        let haptics = config
+            .haptic_strength
+            .and_then(|strength| Haptics::new(&touchpad, strength));
+        let mut light_controller = if !self.light_enabled || self.dry_run {
+            None
+        } else {
+            Some(open_light(ids.i2c_id, &config)?)
+        };
+        // custom layouts can use any key, so enable exactly the ones which can be sent
+        let keys = keys_to_enable(
+            std::iter::once(&layout).chain(layouts.iter().map(|(_, layout)| layout)),
+            &config,
+        );
+        let repeat = config
+            .repeat_delay
+            .filter(|_| config.kernel_repeat)
+            .map(|delay| (delay, config.repeat_interval));
+        let key_simulator = KeySimulator::create(&keys, config.keyboard_id, repeat)
+            .map_err(Error::CouldntCreateKeyboardDevice)?;
+        // tapping them would do nothing, which is worse than not starting
+        let missing_keys = missing_keys(&keys, key_simulator.keys());
+        if !missing_keys.is_empty() {
+            return Err(Error::CouldntEnableKeys(missing_keys));
+        }
+        info!(
+            devnode = key_simulator.devnode().unwrap_or("unknown"),
+            syspath = key_simulator.syspath().unwrap_or("unknown"),
+            "created the virtual keyboard"
+        );
+        // pick up where we left off before the last restart
+        let saved_state = config::state_path()
+            .and_then(|path| SavedState::load(&path))
+            .unwrap_or_default();
+        let max_brightness = light_controller
+            .as_ref()
+            .map_or(MAX_BRIGHTNESS, NumpadLight::max_brightness);
+        let brightness = match self.brightness {
+            Some(InitialBrightness::Level(level)) => level,
+            Some(InitialBrightness::Percent(percent)) => {
+                numpad_light::percent_to_level(percent, max_brightness, config.brightness_gamma)
+            }
+            None => match &mut light_controller {
+                Some(light_controller) => {
+                    current_brightness(light_controller, saved_state.brightness)
+                }
+                None => saved_state.brightness,
+            },
+        }
+        // the saved brightness may be from a light with more levels
+        .min(max_brightness);
+        let mut state = NumpadState::new();
+        state.is_active = saved_state.is_active;
+        if config.sync_numlock
+            && let Some(numlock) = key_simulation::system_numlock()
+        {
+            // the user may have toggled it while we weren't running
+            state.is_active = numlock;
+        }
+        state.slot = touchpad.current_slot().unwrap_or(0) as usize;
+        if let Some(light_controller) = &mut light_controller {
+            if state.is_active || config.light_always_on {
+                light_controller.turn_on()
+            } else {
+                light_controller.turn_off()
+            }
+            .map_err(Error::CouldntControlLight)?;
+            light_controller
+                .set_brightness(brightness)
+                .map_err(Error::CouldntControlLight)?;
+        }
+        let mut number_pad =
+            NumberPad::from_parts(touchpad, key_simulator, light_controller, layout, config);
+        // the time it was active before the restart belongs to the last run
+        number_pad.active_since = state.is_active.then(Instant::now);
+        number_pad.state = state;
+        number_pad.axes = axes;
+        number_pad.touch_major_range = touch_major_range;
+        number_pad.haptics = haptics;
+        number_pad.brightness = brightness;
+        number_pad.state_path = config::state_path().filter(|_| !self.dry_run);
+        number_pad.passthrough = self.passthrough;
+        number_pad.dry_run = self.dry_run;
+        number_pad.trace_raw = self.trace_raw;
+        number_pad.keys = number_pad.key_simulator.keys().to_vec();
+        for (name, layout) in layouts {
+            number_pad.add_layout(name, layout);
+        }
+        number_pad.builder = Some(self);
+        Ok(number_pad)
+    }
 }
 
 impl NumberPad {
-    const MIN_DRAG_DISTANCE: f64 = 30.0;
-    const HOLD_DURATION: Duration = Duration::from_millis(250);
+    /// Create a number pad configured by the config file, the environment and the saved state.
     pub fn new() -> std::result::Result<Self, Error> {
-        let ids = get_touchpad_id().map_err(Error::TouchpadNotFound)?;
-        let device_path = format!("/dev/input/event{}", ids.ev_id);
-        let touchpad =
-            Device::new_from_path(&device_path).map_err(|e| Error::CouldntOpenTouchpaddDevice {
-                device_name: device_path.to_string(),
-                error: e,
-            })?;
-        let mut light_controller =
-            NumpadLight::new(ids.i2c_id).map_err(Error::CouldntConnectToNumpadLight)?;
-        let key_simulator = KeySimulator::new().map_err(Error::CouldntCreateKeyboardDevice)?;
-        light_controller.turn_off().unwrap();
-        light_controller.set_brightness(MAX_BRIGHTNESS).unwrap();
-        Ok(Self {
+        Self::builder().build()
+    }
+
+    pub fn builder() -> NumberPadBuilder {
+        NumberPadBuilder::new()
+    }
+
+    /// The device file of the virtual keyboard the numpad types with, see [KeySimulator::devnode].
+    pub fn keyboard_devnode(&self) -> Option<&str> {
+        self.key_simulator.devnode()
+    }
+
+    /// The sysfs path of the virtual keyboard, see [KeySimulator::syspath].
+    pub fn keyboard_syspath(&self) -> Option<&str> {
+        self.key_simulator.syspath()
+    }
+}
+
+impl<T: GrabControl, K: KeyOutput, L: LightOutput> NumberPad<T, K, L> {
+    /// Create a number pad from its parts, without touching anything but them. It starts out inactive,
+    /// at full brightness, with the layout in raw device coordinates, and it doesn't save its state.
+    /// The light isn't set up either, so it should be in a state matching that. With `kernel_repeat`
+    /// set, the key output is expected to repeat held keys itself.
+    pub fn from_parts(
+        touchpad: T,
+        key_output: K,
+        light: Option<L>,
+        layout: Layout<Action>,
+        config: Config,
+    ) -> Self {
+        let keys = keys_to_enable([&layout], &config);
+        let brightness = light.as_ref().map_or(MAX_BRIGHTNESS, L::max_brightness);
+        NumberPad {
             touchpad,
-            key_simulator,
-            light_controller,
+            key_simulator: key_output,
+            light_controller: light,
             state: NumpadState::new(),
-            layout: default_numpad_layout(),
-            holding_key: None,
-            brightness: MAX_BRIGHTNESS,
-        })
+            layouts: vec![(MAIN_LAYOUT_NAME.to_string(), layout)],
+            layout_index: 0,
+            axes: None,
+            touch_major_range: None,
+            held_modifiers: Vec::new(),
+            modifiers: Vec::new(),
+            last_press: None,
+            next_repeat: None,
+            flash_end: None,
+            blink: None,
+            light_restore: None,
+            brightness,
+            config,
+            brightness_drag_levels: 0,
+            last_activation_tap: None,
+            breath: None,
+            is_grab_deferred: false,
+            haptics: None,
+            commands: None,
+            status_senders: Vec::new(),
+            state_path: None,
+            stats: Stats::default(),
+            active_since: None,
+            passthrough: false,
+            dry_run: false,
+            trace_raw: false,
+            is_paused: false,
+            handles_signals: true,
+            is_shutting_down: false,
+            #[cfg(feature = "notify")]
+            notification: BrightnessNotification::new(),
+            keys,
+            builder: None,
+        }
+    }
+
+    /// Take over the settings from the config which can change while the number pad is running.
+    /// The light's address, model and retries only take effect once the light is connected to again.
+    fn apply_config(&mut self, mut config: Config) {
+        // the keyboard device repeats held keys itself or not, which can't change without recreating it
+        config.kernel_repeat = self.config.kernel_repeat;
+        self.config = config;
     }
 
-    fn stop_holding_key(&mut self) {
-        if let Some(key) = self.holding_key {
-            self.key_simulator.keys_up(&[key]);
-            self.holding_key = None;
+    pub fn status(&self) -> Status {
+        Status {
+            is_active: self.state.is_active,
+            is_paused: self.is_paused,
+            brightness: self.brightness,
+            max_brightness: self.max_brightness(),
+            layout: self.layout_index,
+            layout_names: self.layout_names(),
         }
     }
 
-    fn is_drag_down(&self) -> bool {
-        if self.state.pos_y > self.state.last_touch.pos_y {
-            true
-        } else {
-            false
+    /// The highest brightness level the light has, or [MAX_BRIGHTNESS] if there's no light.
+    pub fn max_brightness(&self) -> u8 {
+        self.light_controller
+            .as_ref()
+            .map_or(MAX_BRIGHTNESS, L::max_brightness)
+    }
+
+    /// Whether the light is on, the level it's at and the highest one it has, or None if the light
+    /// isn't controlled. Unlike [NumberPad::brightness], the level is what the light is actually set
+    /// to at the moment, e.g. while it's dimmed.
+    pub fn light_status(&self) -> Option<LightStatus> {
+        self.light_controller.as_ref().map(L::status)
+    }
+
+    /// Whether the numpad is active, i.e. pressing keys.
+    pub fn is_active(&self) -> bool {
+        self.state.is_active
+    }
+
+    /// The brightness level the light is set to while the numpad is active.
+    pub fn brightness(&self) -> u8 {
+        self.brightness
+    }
+
+    /// The key which is being held down because its item has been touched for longer than the hold
+    /// duration, if any.
+    pub fn holding_key(&self) -> Option<EV_KEY> {
+        match self.state.phase {
+            Phase::Holding(key) => key,
+            _ => None,
         }
     }
 
-    fn is_drag_up(&self) -> bool {
-        if self.state.pos_y < self.state.last_touch.pos_y {
-            true
-        } else {
-            false
-        }
-    }
-    fn lift(&mut self) {
-        if self.state.is_dragging {
-            self.state.is_dragging = false;
-            // if the drag started in the numlock area it means we should adjust the brightness
-            if self.state.is_active && self.state.last_touch.key == Some(EV_KEY::KEY_NUMLOCK) {
-                if self.is_drag_up() {
-                    if self.brightness < MAX_BRIGHTNESS {
-                        self.brightness += 1;
-                        self.light_controller
-                            .set_brightness(self.brightness)
-                            .unwrap();
-                    }
-                } else if self.is_drag_down() {
-                    if self.brightness > 0 {
-                        self.brightness -= 1;
-                        self.light_controller
-                            .set_brightness(self.brightness)
-                            .unwrap();
-                    }
-                }
-                // we didn't stop the grab if it started from
-                self.touchpad.grab(evdev_rs::GrabMode::Grab).unwrap();
-            }
+    /// How many times each key was pressed, how often the numpad was toggled and how long it was active.
+    pub fn stats(&self) -> Stats {
+        let mut stats = self.stats.clone();
+        if let Some(since) = self.active_since {
+            stats.active_time += since.elapsed();
+        }
+        stats
+    }
 
-            return;
-        } else if self.holding_key.is_some() {
-            self.stop_holding_key();
-            return;
-        } else if let Some(key) = self.layout.get_item(self.state.pos_x, self.state.pos_y) {
-            match key {
-                EV_KEY::KEY_NUMLOCK => {
-                    self.state.is_active = !self.state.is_active;
-                    // numlock integration?
-                    //self.key_simulator.keys_press(&[EV_KEY::KEY_NUMLOCK]);
-                    if self.state.is_active {
-                        self.light_controller.turn_on().unwrap();
-                    } else {
-                        self.light_controller.turn_off().unwrap();
-                        // we might still be grabbing if the user hasn't done a drag; ensure we ungrab
-                        self.touchpad.grab(GrabMode::Ungrab).unwrap();
-                    }
-                }
-                _ => {
-                    if self.state.is_active {
-                        //  press the desired key
-                        self.key_simulator.keys_press(&[key])
-                    }
-                }
+    /// Add a layout which can be switched to, e.g. with a NEXT_LAYOUT item or a [Command]. A layout
+    /// with the same name is replaced.
+    pub fn add_layout(&mut self, name: impl Into<String>, layout: Layout<Action>) {
+        let name = name.into();
+        match self.layouts.iter_mut().find(|(other, _)| *other == name) {
+            Some((_, other)) => *other = layout,
+            None => {
+                self.layouts.push((name, layout));
+                self.notify_status();
             }
         }
     }
-    fn handle_touchpad_event(&mut self, event: InputEvent) {
-        match event.event_code {
-            EventCode::EV_ABS(EV_ABS::ABS_MT_POSITION_X) => {
-                self.state.pos_x = event.value as usize;
+
+    /// The names of the layouts, starting with the main one, which is called [MAIN_LAYOUT_NAME].
+    pub fn layout_names(&self) -> Vec<String> {
+        self.layouts.iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    fn layout(&self) -> &Layout<Action> {
+        &self.layouts[self.layout_index].1
+    }
+
+    /// Switch to another layout, releasing anything held on the current one.
+    fn select_layout(&mut self, index: usize) -> Result<(), Error> {
+        if index == self.layout_index {
+            return Ok(());
+        }
+        self.stop_holding_key()?;
+        self.layout_index = index;
+        info!(layout = self.layouts[index].0, "switched the layout");
+        if self.state.phase == Phase::Idle {
+            // the next touch grabs again if it lands on the new layout
+            self.grab(GrabMode::Ungrab)?;
+        } else {
+            // the finger is on whatever was under it in the old layout
+            self.ignore_touch()?;
+        }
+        self.notify_status();
+        Ok(())
+    }
+
+    fn next_layout(&mut self) -> Result<(), Error> {
+        self.select_layout((self.layout_index + 1) % self.layouts.len())
+    }
+
+    /// Get a receiver for the status, which gets the new status every time the numpad is activated
+    /// or deactivated, its brightness or its layout changes or a layout is added, no matter if by touch
+    /// or by a [Command].
+    pub fn subscribe(&mut self) -> Receiver<Status> {
+        let (sender, receiver) = mpsc::channel();
+        self.status_senders.push(sender);
+        receiver
+    }
+
+    /// Get a controller for sending commands to the number pad from other threads, e.g. to toggle it from a
+    /// keyboard shortcut. The commands are handled by [NumberPad::enter_input_loop].
+    pub fn controller(&mut self) -> std::io::Result<Controller> {
+        let commands = match &mut self.commands {
+            Some(commands) => commands,
+            None => {
+                let (sender, receiver) = mpsc::channel();
+                let (wake_receiver, wake_sender) = UnixStream::pair()?;
+                wake_receiver.set_nonblocking(true)?;
+                wake_sender.set_nonblocking(true)?;
+                self.commands.insert(CommandChannel {
+                    sender,
+                    receiver,
+                    wake_sender,
+                    wake_receiver,
+                })
             }
-            EventCode::EV_ABS(EV_ABS::ABS_MT_POSITION_Y) => {
-                self.state.pos_y = event.value as usize;
+        };
+        Ok(Controller {
+            sender: commands.sender.clone(),
+            waker: commands.wake_sender.try_clone()?,
+        })
+    }
+
+    /// Stop doing anything until [NumberPad::resume] is called, without changing whether the numpad is
+    /// active: release whatever is held, ungrab the touchpad, turn off the light and ignore the touchpad.
+    pub fn pause(&mut self) -> Result<(), Error> {
+        if self.is_paused {
+            return Ok(());
+        }
+        info!("pausing the numpad");
+        self.is_paused = true;
+        self.notify_status();
+        self.stop_holding_key()?;
+        self.modifiers.clear();
+        self.blink = None;
+        self.flash_end = None;
+        // the touch goes on without us
+        if self.state.phase != Phase::Idle {
+            self.state.phase = Phase::Dragging;
+        }
+        self.grab(GrabMode::Ungrab)?;
+        self.with_light(L::turn_off)
+    }
+
+    /// Pick up where [NumberPad::pause] left off; a touch which is still going on is ignored.
+    pub fn resume(&mut self) -> Result<(), Error> {
+        if !self.is_paused {
+            return Ok(());
+        }
+        info!("resuming the numpad");
+        self.is_paused = false;
+        self.notify_status();
+        if self.is_lit() && !self.state.is_idle {
+            self.with_light(L::turn_on)?;
+        }
+        Ok(())
+    }
+
+    /// Get back into a consistent state after the system woke up from a suspend, during which the
+    /// touchpad and the light may have been reset: forget the touch that was going on, ungrab the
+    /// touchpad and set the light to what it should be. If the light isn't back yet, this is retried a
+    /// few times in the background, backing off in between. A touchpad which didn't come back is
+    /// reopened by the input loop as usual.
+    pub fn restore_after_sleep(&mut self) -> Result<(), Error> {
+        info!("the system woke up, restoring the numpad");
+        self.stop_holding_key()?;
+        self.state.phase = Phase::Idle;
+        self.state.last_event = Instant::now();
+        self.state.is_idle = false;
+        self.state.is_dimmed = false;
+        self.flash_end = None;
+        self.blink = None;
+        self.grab(GrabMode::Ungrab)?;
+        self.light_restore = None;
+        self.restore_light(0);
+        Ok(())
+    }
+
+    /// Set the light to what it should be, or try again later if that fails, unless that was the
+    /// last attempt.
+    fn restore_light(&mut self, attempt: u32) {
+        let (is_lit, brightness) = (self.is_lit() && !self.is_paused, self.brightness);
+        let result = self.with_light(|light| {
+            if is_lit {
+                light.turn_on()?;
+                light.set_brightness(brightness)
+            } else {
+                light.turn_off()
             }
-            EventCode::EV_KEY(EV_KEY::BTN_TOOL_FINGER) => {
-                if event.value == 0 {
-                    // finger lifted
-                    self.state.is_lifted = true;
-                    self.lift();
-                } else {
-                    if self.state.is_dragging {
-                        // if we're dragging, it means the user has a hand on the touchpad
-                        // and its likely they're trying to do some kind of gesture, so we don't need to grab anything.
-                        return;
-                    }
-                    // finger is on the touchpad
-                    self.state.last_touch.pos_x = self.state.pos_x;
-                    self.state.last_touch.pos_y = self.state.pos_y;
-                    self.state.last_touch.time = Instant::now();
-                    self.state.is_lifted = false;
-                    self.state.last_touch.key =
-                        self.layout.get_item(self.state.pos_x, self.state.pos_y);
-                    if self.state.is_active
-                        && // if the user touches a place which is not in the layout it is considered as normal mouse movement; we don't need to grab.
-                        self.state.last_touch.key.is_some()
-                    {
-                        // NOTE: MUST ACTIVATE THE GRAB HERE RATHER THAN SIMPLY GRABBING WHEN ENABLED
-                        // AND THEN UNGRABBING/GRABBING WHEN NECESSARY.
-                        // IF WE GRAB WHEN ENABLED, DRAGGING WON'T WORK FOR SOME REASON.
-                        self.touchpad.grab(evdev_rs::GrabMode::Grab).unwrap();
-                    }
-                }
+        });
+        if let Err(error) = result {
+            if attempt + 1 < LIGHT_RESTORE_ATTEMPTS {
+                debug!(attempt, "couldn't restore the light yet: {}", error);
+                let delay = LIGHT_RESTORE_DELAY * 2u32.pow(attempt);
+                self.light_restore = Some((Instant::now() + delay, attempt + 1));
+            } else {
+                warn!("couldn't restore the light after waking up: {}", error);
             }
+        }
+    }
 
-            EventCode::EV_MSC(EV_MSC::MSC_TIMESTAMP) => {
-                // the user is holding; check if they moved far enough from the first touch
-                fn dist(x1: usize, y1: usize, x2: usize, y2: usize) -> f64 {
-                    ((x1 as f64 - x2 as f64).powi(2) + (y1 as f64 - y2 as f64).powi(2)).sqrt()
-                }
-                if self.state.is_lifted {
-                    return;
-                }
-                if !self.state.is_dragging
-                    && dist(
-                        self.state.pos_x,
-                        self.state.pos_y,
-                        self.state.last_touch.pos_x,
-                        self.state.last_touch.pos_y,
-                    ) >= Self::MIN_DRAG_DISTANCE
-                {
-                    // if the touched key is numlock, it means the user is trying to change the brightness,
-                    // so we don't need to release the grab on the touchpad
-                    if self.state.last_touch.key != Some(EV_KEY::KEY_NUMLOCK) {
-                        // the user wants to move the cursor; ungrab
-                        self.touchpad.grab(GrabMode::Ungrab).unwrap();
-                    }
-                    self.state.is_dragging = true;
-                    self.stop_holding_key();
-                } else if self.state.is_active
-                    && !self.state.is_dragging
-                    && Instant::now() - self.state.last_touch.time > Self::HOLD_DURATION
-                    && self.holding_key.is_none()
-                {
-                    if let Some(key) = self.state.last_touch.key {
-                        self.holding_key = Some(key);
-                        match key {
-                            EV_KEY::KEY_NUMLOCK => {
-                                // do something
-                            }
-
-                            _ => self.key_simulator.keys_down(&[key]),
-                        }
-                    }
-                }
-            }
-            _ => (),
+    /// Keep track of the fingers while paused, so that they don't look like they jumped once we're resumed.
+    fn follow_paused_touch(&mut self, event: &InputEvent) {
+        self.state.track_contacts(event);
+        if event.event_code != EventCode::EV_SYN(EV_SYN::SYN_REPORT) {
+            return;
+        }
+        self.state.contact_count = self.state.count_contacts();
+        if self.state.contact_count == 0 {
+            self.state.touch_slot = None;
+            self.state.phase = Phase::Idle;
+        } else if self.state.phase == Phase::Idle {
+            self.state.phase = Phase::Dragging;
         }
     }
-    pub fn enter_input_loop(&mut self) -> std::io::Result<()> {
-        let mut fds = pollfd {
-            fd: self.touchpad.file().as_raw_fd(),
-            events: POLLIN,
-            revents: 0,
-        };
-        loop {
-            // wait for some event to happen so that we don't busywait2
-            unsafe {
-                let result = libc::poll(&mut fds, 1, -1);
-                if result < 0 {
-                    panic!("error: {}", std::io::Error::last_os_error());
-                }
-            }
 
-            // read all the events that happened
-            while let Ok((_read_flags, event)) = self.touchpad.next_event(ReadFlag::NORMAL) {
-                self.handle_touchpad_event(event);
+    /// Set whether every event read from the touchpad is printed before it's handled, as its time, code
+    /// and value on one line (e.g. `1712345678.123456 ABS_MT_POSITION_X 1234`), for finding out what
+    /// a touchpad reports when it confuses the driver.
+    pub fn set_trace_raw(&mut self, trace_raw: bool) {
+        self.trace_raw = trace_raw;
+    }
+
+    /// Set whether dragging down from the activation key makes the light brighter and dragging up
+    /// darker; by default it's the other way around.
+    pub fn set_invert_brightness_drag(&mut self, invert_brightness_drag: bool) {
+        self.config.invert_brightness_drag = invert_brightness_drag;
+    }
+
+    /// Set whether the light stays on while the numpad is inactive, for seeing the printed keys without
+    /// pressing them; otherwise it's only on while the numpad is active. Either way it turns off when
+    /// the numpad is idle or paused.
+    pub fn set_light_always_on(&mut self, light_always_on: bool) -> Result<(), Error> {
+        self.config.light_always_on = light_always_on;
+        if self.state.is_active || self.is_paused || self.state.is_idle {
+            return Ok(());
+        }
+        if light_always_on {
+            self.with_light(L::turn_on)?;
+            let brightness = self.brightness;
+            self.with_light(|light| light.set_brightness(brightness))
+        } else {
+            self.with_light(L::turn_off)
+        }
+    }
+
+    /// Whether the light should be on, as long as the numpad isn't idle or paused.
+    fn is_lit(&self) -> bool {
+        self.state.is_active || self.config.light_always_on
+    }
+
+    /// Set how long a key needs to be touched before it's considered held.
+    pub fn set_hold_duration(&mut self, hold_duration: Duration) {
+        self.config.hold_duration = hold_duration;
+    }
+
+    /// Set how far the finger needs to move from where it first touched for the touch to be a drag.
+    pub fn set_drag_distance(&mut self, drag_distance: f64) {
+        self.config.drag_distance = drag_distance;
+    }
+
+    /// Set how far past the edge of the touched item the finger needs to be when it's lifted for the tap to
+    /// be on the item it moved to instead, see [Config::key_hysteresis].
+    pub fn set_key_hysteresis(&mut self, key_hysteresis: f64) {
+        self.config.key_hysteresis = key_hysteresis;
+    }
+
+    /// Set which of the layout's items toggles the numpad when tapped and changes the brightness
+    /// when dragged up or down from, as [Action::ToggleActive] items always do.
+    pub fn set_activation_key(&mut self, activation_key: impl Into<Action>) {
+        self.config.activation_key = activation_key.into();
+    }
+
+    /// Set how large the top right corner of the touchpad is, as a fraction of its width and height, from
+    /// which swiping down and to the left toggles the numpad, or None to not toggle it by swiping.
+    pub fn set_activation_swipe(&mut self, activation_swipe: Option<f64>) {
+        self.config.activation_swipe = activation_swipe;
+    }
+
+    /// Set how long the numpad can go untouched while active before its light is turned off,
+    /// or None to never turn it off.
+    pub fn set_idle_timeout(&mut self, idle_timeout: Option<Duration>) {
+        self.config.idle_timeout = idle_timeout;
+    }
+
+    /// Set how long the numpad can go untouched while active before its light is dimmed to the lowest
+    /// level, or None to never dim it. The next touch restores the brightness.
+    pub fn set_dim_timeout(&mut self, dim_timeout: Option<Duration>) {
+        self.config.dim_timeout = dim_timeout;
+    }
+
+    /// Set how long a key needs to be held before it starts repeating and how often it repeats
+    /// from then on, or None to never repeat.
+    pub fn set_key_repeat(&mut self, repeat: Option<(Duration, Duration)>) {
+        match repeat {
+            Some((delay, interval)) => {
+                self.config.repeat_delay = Some(delay);
+                self.config.repeat_interval = interval;
             }
+            None => self.config.repeat_delay = None,
         }
     }
+
+    /// Set how large a contact needs to be, relative to the largest one the touchpad can report,
+    /// to be considered a palm and ignored, or None to never ignore contacts because of their size.
+    pub fn set_palm_size(&mut self, palm_size: Option<f64>) {
+        self.config.palm_size = palm_size;
+    }
+
+    fn save_state(&self) {
+        let saved_state = SavedState {
+            brightness: self.brightness,
+            is_active: self.state.is_active,
+        };
+        if let Some(path) = &self.state_path {
+            // not being able to save the state isn't worth dying over; we'll just start with the defaults
+            let _ = saved_state.save(path);
+        }
+    }
+
+    fn grab(&mut self, mode: GrabMode) -> Result<(), Error> {
+        if self.dry_run {
+            match mode {
+                GrabMode::Grab => println!("would grab the touchpad"),
+                GrabMode::Ungrab => println!("would ungrab the touchpad"),
+            }
+            return Ok(());
+        }
+        if self.passthrough {
+            return Ok(());
+        }
+        match mode {
+            GrabMode::Grab => debug!("grabbing the touchpad"),
+            GrabMode::Ungrab => debug!("ungrabbing the touchpad"),
+        }
+        self.touchpad.grab(mode).map_err(Error::CouldntGrabTouchpad)
+    }
+
+    /// Send keys with the given operation, unless we're in passthrough mode or a dry run.
+    fn with_keys(
+        &mut self,
+        operation: impl FnOnce(&mut K) -> std::io::Result<()>,
+    ) -> Result<(), Error> {
+        if self.passthrough || self.dry_run {
+            return Ok(());
+        }
+        operation(&mut self.key_simulator).map_err(Error::CouldntEmitKeys)
+    }
+
+    /// Run the given operation on the light, unless we were asked not to control it.
+    fn with_light(
+        &mut self,
+        operation: impl FnOnce(&mut L) -> Result<(), i2cdev::linux::LinuxI2CError>,
+    ) -> Result<(), Error> {
+        match &mut self.light_controller {
+            Some(light_controller) => {
+                operation(light_controller).map_err(Error::CouldntControlLight)
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn set_brightness(&mut self, brightness: u8) -> Result<(), Error> {
+        let fade_duration = self.config.fade_duration;
+        self.with_light(|light| light.fade_to(brightness, fade_duration))?;
+        // this replaces whatever brightness the flash would have restored, and the breathing starts over
+        // from the new one
+        self.flash_end = None;
+        self.breath = None;
+        debug!(brightness, "changed the brightness");
+        self.brightness = brightness;
+        self.save_state();
+        self.notify_status();
+        Ok(())
+    }
+
+    fn notify_status(&mut self) {
+        let status = self.status();
+        // forget about the subscribers which stopped listening
+        self.status_senders
+            .retain(|sender| sender.send(status.clone()).is_ok());
+    }
+
+    /// Dim the light for a moment, as feedback for a key press; [NumberPad::handle_timers] restores it.
+    fn start_flash(&mut self) -> Result<(), Error> {
+        if self.brightness == 0 || self.light_controller.is_none() {
+            return Ok(());
+        }
+        let dimmed = self.brightness / 2;
+        self.with_light(|light| light.set_brightness(dimmed))?;
+        self.flash_end = Some(Instant::now() + FLASH_DURATION);
+        Ok(())
+    }
+
+    /// Whether the light should be breathing now: it's enabled and the numpad is active, with the light
+    /// at its brightness rather than flashing, blinking, fading, dimmed or off, and the brightness isn't
+    /// being dragged, which should show the actual level.
+    fn should_breathe(&self) -> bool {
+        self.config.breathing_period.is_some()
+            && self.state.is_active
+            && !self.state.is_idle
+            && !self.state.is_dimmed
+            && !self.is_paused
+            && self.state.phase != Phase::BrightnessDrag
+            && self.flash_end.is_none()
+            && self.blink.is_none()
+            && self
+                .light_controller
+                .as_ref()
+                .is_some_and(|light| light.next_fade_update().is_none())
+    }
+
+    /// When the breathing light should be updated next, if it should breathe.
+    fn breathing_deadline(&self) -> Option<Instant> {
+        if !self.should_breathe() {
+            // it may need to be stopped
+            return self.breath.map(|_| Instant::now());
+        }
+        Some(
+            self.breath
+                .map_or_else(Instant::now, |breath| breath.next_update),
+        )
+    }
+
+    /// Set the breathing light to the level it should be at by now, starting to breathe if it wasn't,
+    /// or stop breathing if it shouldn't anymore.
+    fn update_breathing(&mut self) -> Result<(), Error> {
+        let (Some(period), true) = (self.config.breathing_period, self.should_breathe()) else {
+            return self.stop_breathing();
+        };
+        let now = Instant::now();
+        let breath = self.breath.get_or_insert(Breath {
+            start: now,
+            next_update: now,
+            level: self.brightness,
+        });
+        // a cosine starting at the top, so that it starts out at the brightness
+        let phase = (now - breath.start).as_secs_f64() / period.as_secs_f64();
+        let depth = self.brightness as f64 * self.config.breathing_depth;
+        let dip = depth * (1.0 - (phase * std::f64::consts::TAU).cos()) / 2.0;
+        let level = (self.brightness as f64 - dip).round() as u8;
+        breath.next_update = now + BREATHING_STEP;
+        if level != std::mem::replace(&mut breath.level, level) {
+            self.with_light(|light| light.set_brightness(level))?;
+        }
+        Ok(())
+    }
+
+    /// Stop breathing, restoring the brightness unless something else is in charge of the light now,
+    /// e.g. it's being dimmed, turned off or flashed.
+    fn stop_breathing(&mut self) -> Result<(), Error> {
+        let Some(breath) = self.breath.take() else {
+            return Ok(());
+        };
+        let is_in_charge = self.is_lit()
+            && !self.state.is_idle
+            && !self.state.is_dimmed
+            && !self.is_paused
+            && self.flash_end.is_none()
+            && self.blink.is_none()
+            && self
+                .light_controller
+                .as_ref()
+                .is_some_and(|light| light.next_fade_update().is_none());
+        if is_in_charge && breath.level != self.brightness {
+            let brightness = self.brightness;
+            self.with_light(|light| light.set_brightness(brightness))?;
+        }
+        Ok(())
+    }
+
+    /// Blink the light as configured, as a cue that the numpad was activated; the light has to be on.
+    /// [NumberPad::handle_timers] does the blinking, and it ends with the light on at the current brightness.
+    fn start_blinking(&mut self) {
+        if self.config.activation_blinks == 0 || self.light_controller.is_none() {
+            return;
+        }
+        self.blink = Some(Blink {
+            toggles_left: 2 * self.config.activation_blinks,
+            next_toggle: Instant::now() + self.config.blink_interval,
+        });
+    }
+
+    /// Turn the light off or on for the blinking in progress.
+    fn toggle_blink(&mut self) -> Result<(), Error> {
+        let Some(blink) = &mut self.blink else {
+            return Ok(());
+        };
+        blink.toggles_left -= 1;
+        blink.next_toggle += self.config.blink_interval;
+        if blink.toggles_left == 0 {
+            self.blink = None;
+            // make sure it ends up as it should be, whatever happened in between
+            let brightness = self.brightness;
+            self.with_light(L::turn_on)?;
+            self.with_light(|light| light.set_brightness(brightness))
+        } else {
+            self.with_light(L::toggle)
+        }
+    }
+
+    fn stop_holding_key(&mut self) -> Result<(), Error> {
+        self.next_repeat = None;
+        if let Phase::Holding(Some(key)) = self.state.phase {
+            // the finger is still down, so lifting it mustn't tap
+            self.state.phase = Phase::Holding(None);
+            debug!(?key, "releasing the held key");
+            let keys: Vec<EV_KEY> = std::iter::once(key)
+                .chain(self.held_modifiers.drain(..))
+                .collect();
+            self.with_keys(|output| output.keys_up(&keys))?;
+        }
+        if let Phase::Chord(index) = self.state.phase {
+            self.state.phase = Phase::Dragging;
+            let keys: Vec<EV_KEY> = self.config.chords[index]
+                .press
+                .iter()
+                .rev()
+                .copied()
+                .collect();
+            debug!(?keys, "releasing the chord");
+            self.with_keys(|output| output.keys_up(&keys))?;
+        }
+        Ok(())
+    }
+
+    /// A sticky modifier was tapped: arm it if it's off, lock it if it's armed and turn it off if it's locked.
+    fn tap_modifier(&mut self, key: EV_KEY) {
+        match self
+            .modifiers
+            .iter()
+            .position(|(modifier, _)| *modifier == key)
+        {
+            None => self.modifiers.push((key, ModifierState::Armed)),
+            Some(index) => match self.modifiers[index].1 {
+                ModifierState::Armed => self.modifiers[index].1 = ModifierState::Locked,
+                ModifierState::Locked => {
+                    self.modifiers.remove(index);
+                }
+            },
+        }
+        debug!(modifiers = ?self.modifiers, "tapped a modifier");
+    }
+
+    /// The modifiers to press along with a key, i.e. the armed ones, which are turned off, and the locked ones.
+    fn take_modifiers(&mut self) -> Vec<EV_KEY> {
+        let modifiers = self.modifiers.iter().map(|(key, _)| *key).collect();
+        self.modifiers
+            .retain(|(_, state)| *state == ModifierState::Locked);
+        modifiers
+    }
+
+    /// Activate or deactivate the numpad, as touching the numlock key does.
+    fn set_active(&mut self, is_active: bool) -> Result<(), Error> {
+        if is_active != self.state.is_active {
+            self.stats.toggles += 1;
+            if is_active {
+                self.active_since = Some(Instant::now());
+            } else if let Some(since) = self.active_since.take() {
+                self.stats.active_time += since.elapsed();
+            }
+        }
+        self.state.is_active = is_active;
+        self.save_state();
+        self.notify_status();
+        if self.config.sync_numlock {
+            self.sync_system_numlock()?;
+        }
+        if self.state.is_active {
+            info!("numpad activated");
+            // the light comes on once we're resumed
+            if !self.is_paused {
+                self.with_light(L::turn_on)?;
+                self.start_blinking();
+            }
+        } else {
+            info!("numpad deactivated");
+            self.blink = None;
+            // we may be deactivated by a command while a key is held
+            self.stop_holding_key()?;
+            self.modifiers.clear();
+            if !self.config.light_always_on {
+                self.with_light(L::turn_off)?;
+            }
+            // we might still be grabbing if the user hasn't done a drag; ensure we ungrab
+            self.grab(GrabMode::Ungrab)?;
+        }
+        Ok(())
+    }
+
+    /// Toggle the system's NumLock if it doesn't match whether the numpad is active.
+    fn sync_system_numlock(&mut self) -> Result<(), Error> {
+        // checking first means we never flip it the wrong way, e.g. after the user toggled it with their keyboard.
+        // if we can't tell its state we assume they were in sync until now
+        if key_simulation::system_numlock() != Some(self.state.is_active) {
+            debug!("toggling the system's numlock");
+            self.with_keys(|output| output.keys_press(&[EV_KEY::KEY_NUMLOCK]))?;
+        }
+        Ok(())
+    }
+
+    fn handle_command(&mut self, command: Command) -> Result<(), Error> {
+        debug!(?command, "handling a command");
+        // a command counts as using the numpad, so it shouldn't be turned off right away for being idle;
+        // after a suspend the light may not be back yet though, which restoring it takes care of
+        if command != Command::RestoreAfterSleep {
+            self.wake_up()?;
+        }
+        match command {
+            Command::Toggle => self.set_active(!self.state.is_active),
+            Command::SetActive(is_active) => self.set_active(is_active),
+            Command::SetPaused(true) => self.pause(),
+            Command::SetPaused(false) => self.resume(),
+            Command::SetBrightness(brightness) => {
+                self.set_brightness(brightness.min(self.max_brightness()))
+            }
+            Command::NextLayout => self.next_layout(),
+            Command::SetLayout(name) => {
+                match self.layouts.iter().position(|(other, _)| *other == name) {
+                    Some(index) => self.select_layout(index),
+                    None => {
+                        warn!(name, "there's no such layout");
+                        Ok(())
+                    }
+                }
+            }
+            Command::PressKey(key) => self.press_key(key),
+            Command::RestoreAfterSleep => self.restore_after_sleep(),
+            Command::Shutdown => {
+                self.is_shutting_down = true;
+                Ok(())
+            }
+        }
+    }
+
+    /// Handle the commands sent by the controllers since we last checked.
+    fn handle_commands(&mut self) {
+        let Some(commands) = &self.commands else {
+            return;
+        };
+        let mut buffer = [0; 64];
+        while matches!((&commands.wake_receiver).read(&mut buffer), Ok(1..)) {}
+        let received: Vec<Command> = commands.receiver.try_iter().collect();
+        for command in received {
+            if let Err(error) = self.handle_command(command) {
+                warn!("error while handling a command: {}", error);
+            }
+        }
+    }
+
+    /// Raise or lower the brightness by a level, if it isn't at the end of the range already.
+    fn step_brightness(&mut self, up: bool) -> Result<(), Error> {
+        self.change_brightness(if up { 1 } else { -1 })
+    }
+
+    /// Raise (or lower, if negative) the brightness by the given number of levels, as far as the range goes.
+    fn change_brightness(&mut self, levels: i32) -> Result<(), Error> {
+        let max_brightness = self.max_brightness();
+        let brightness = (self.brightness as i32 + levels).clamp(0, max_brightness as i32) as u8;
+        if brightness == self.brightness {
+            return Ok(());
+        }
+        self.set_brightness(brightness)?;
+        #[cfg(feature = "notify")]
+        if let Err(error) = self.notification.show(brightness, max_brightness) {
+            // e.g. there's no notification server, which just means there's no one to show it to
+            debug!(%error, "couldn't show the brightness notification");
+        }
+        Ok(())
+    }
+
+    /// Switch to the layout with the given index, if there is one.
+    fn switch_layout(&mut self, index: usize) -> Result<(), Error> {
+        if index >= self.layouts.len() {
+            debug!(index, "there's no layout to switch to with this index");
+            return Ok(());
+        }
+        self.select_layout(index)
+    }
+
+    /// Press and release the key as tapping it does, including the modifiers and the feedback, if the
+    /// numpad is active; otherwise nothing happens, as with a tap. Returns [Error::CouldntPressKey]
+    /// if the key can't be sent, i.e. it's neither in the layouts nor needed by the config.
+    pub fn press_key(&mut self, key: EV_KEY) -> Result<(), Error> {
+        if !self.keys.contains(&key) {
+            return Err(Error::CouldntPressKey(key));
+        }
+        if !self.state.is_active {
+            debug!(?key, "not pressing the key since the numpad is inactive");
+            return Ok(());
+        }
+        self.press(key)
+    }
+
+    fn press(&mut self, key: EV_KEY) -> Result<(), Error> {
+        debug!(?key, "pressing key");
+        self.count_press(key);
+        let keys: Vec<EV_KEY> = self.take_modifiers().into_iter().chain([key]).collect();
+        if self.dry_run {
+            println!("would press {:?}", keys);
+            return Ok(());
+        }
+        self.with_keys(|output| output.keys_press(&keys))?;
+        self.press_feedback()
+    }
+
+    /// Type the character of a `U+` item, see [Config::unicode_input]. Sticky modifiers stay armed for
+    /// the next key, since they'd change what the sequence types.
+    fn type_unicode(&mut self, character: char) -> Result<(), Error> {
+        debug!(?character, "typing a character");
+        if self.dry_run {
+            println!("would type {:?}", character);
+            return Ok(());
+        }
+        let input = self.config.unicode_input.clone();
+        self.with_keys(|output| output.type_unicode(character, &input))?;
+        self.press_feedback()
+    }
+
+    /// Let the user know that a key was pressed, if they asked for it.
+    fn press_feedback(&mut self) -> Result<(), Error> {
+        if let Some(haptics) = &self.haptics
+            && let Err(error) = haptics.play()
+        {
+            // not worth failing the key press over
+            debug!("couldn't vibrate the touchpad: {}", error);
+        }
+        if self.config.key_flash {
+            self.start_flash()?;
+        }
+        Ok(())
+    }
+
+    fn count_press(&mut self, key: EV_KEY) {
+        match self
+            .stats
+            .key_presses
+            .iter_mut()
+            .find(|(other, _)| *other == key)
+        {
+            Some((_, count)) => *count += 1,
+            None => self.stats.key_presses.push((key, 1)),
+        }
+    }
+
+    /// Whether tapping the key now would be the finger bouncing after the last tap, see [Config::debounce].
+    fn is_bounce(&self, key: EV_KEY) -> bool {
+        match (self.last_press, self.config.debounce) {
+            (Some((last_key, time)), Some(debounce)) => {
+                last_key == key && time.elapsed() < debounce
+            }
+            _ => false,
+        }
+    }
+
+    /// When the light should be turned off due to inactivity, if ever.
+    fn idle_deadline(&self) -> Option<Instant> {
+        let idle_timeout = self.config.idle_timeout?;
+        if !self.is_lit() || self.state.is_idle {
+            return None;
+        }
+        Some(self.state.last_event + idle_timeout)
+    }
+
+    /// When the light should be dimmed due to inactivity, if ever.
+    fn dim_deadline(&self) -> Option<Instant> {
+        let dim_timeout = self.config.dim_timeout?;
+        if !self.is_lit() || self.state.is_idle || self.state.is_dimmed {
+            return None;
+        }
+        Some(self.state.last_event + dim_timeout)
+    }
+
+    /// Note that the numpad is being used, undoing whatever being idle did to the light.
+    fn wake_up(&mut self) -> Result<(), Error> {
+        self.state.last_event = Instant::now();
+        let was_idle = std::mem::take(&mut self.state.is_idle);
+        let was_dimmed = std::mem::take(&mut self.state.is_dimmed);
+        if !self.is_lit() {
+            return Ok(());
+        }
+        if was_idle {
+            debug!("the numpad is no longer idle, turning the light back on");
+            self.with_light(L::turn_on)?;
+        }
+        if was_dimmed {
+            debug!("the numpad is no longer idle, restoring the brightness");
+            let (brightness, fade_duration) = (self.brightness, self.config.fade_duration);
+            self.with_light(|light| light.fade_to(brightness, fade_duration))?;
+        }
+        Ok(())
+    }
+
+    /// How long until something needs to be done even if no event arrives, if ever.
+    fn next_timer(&self) -> Option<Duration> {
+        let next_fade_update = self.light_controller.as_ref().and_then(L::next_fade_update);
+        [
+            self.idle_deadline(),
+            self.dim_deadline(),
+            self.next_repeat,
+            self.flash_end,
+            self.blink.map(|blink| blink.next_toggle),
+            self.light_restore.map(|(deadline, _)| deadline),
+            self.breathing_deadline(),
+            next_fade_update,
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+        .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Do whatever is due since the last time the loop woke up.
+    fn handle_timers(&mut self) -> Result<(), Error> {
+        let now = Instant::now();
+        if self.dim_deadline().is_some_and(|deadline| deadline <= now) {
+            info!("the numpad is about to be idle, dimming the light");
+            self.state.is_dimmed = true;
+            self.blink = None;
+            self.flash_end = None;
+            let fade_duration = self.config.fade_duration;
+            self.with_light(|light| light.fade_to(0, fade_duration))?;
+        }
+        if self.idle_deadline().is_some_and(|deadline| deadline <= now) {
+            info!("the numpad is idle, turning off the light");
+            self.state.is_idle = true;
+            self.blink = None;
+            self.with_light(L::turn_off)?;
+        }
+        if self
+            .light_controller
+            .as_ref()
+            .and_then(L::next_fade_update)
+            .is_some_and(|deadline| deadline <= now)
+        {
+            self.with_light(L::update_fade)?;
+        }
+        if self.flash_end.is_some_and(|deadline| deadline <= now) {
+            self.flash_end = None;
+            let brightness = self.brightness;
+            self.with_light(|light| light.set_brightness(brightness))?;
+        }
+        if self.blink.is_some_and(|blink| blink.next_toggle <= now) {
+            self.toggle_blink()?;
+        }
+        if let Some((deadline, attempt)) = self.light_restore
+            && deadline <= now
+        {
+            self.light_restore = None;
+            self.restore_light(attempt);
+        }
+        if self
+            .breathing_deadline()
+            .is_some_and(|deadline| deadline <= now)
+        {
+            self.update_breathing()?;
+        }
+        if let Phase::Holding(Some(key)) = self.state.phase
+            && self.next_repeat.is_some_and(|deadline| deadline <= now)
+        {
+            self.next_repeat = Some(now + self.config.repeat_interval);
+            self.with_keys(|output| output.keys_repeat(&[key]))?;
+        }
+        Ok(())
+    }
+
+    /// Release all the keys, ungrab the touchpad and turn off the light.
+    /// Errors are ignored; releasing keys which aren't down and ungrabbing when we never grabbed is harmless,
+    /// and there's nothing left to do if it fails anyways.
+    fn release(&mut self) {
+        // whatever was held, including modifiers, shouldn't stay down for whoever reads the keys
+        self.next_repeat = None;
+        let _ = self.with_keys(K::release_all);
+        let _ = self.touchpad.grab(GrabMode::Ungrab);
+        let _ = self.with_light(L::turn_off);
+    }
+}
+
+impl NumberPad {
+    /// Load the config and the layouts again and switch to them, keeping the devices open. Anything
+    /// held is released first. If anything can't be loaded, the old config stays in use.
+    /// Keys which weren't used when the number pad was built can't be sent until it's built again.
+    pub fn reload(&mut self) -> Result<(), Error> {
+        let Some(builder) = &self.builder else {
+            debug!("the number pad wasn't built from a config, so there's nothing to reload");
+            return Ok(());
+        };
+        let config = builder.load_config()?;
+        let mut layout = builder.load_main_layout(&config)?;
+        let mut layouts = load_extra_layouts()?;
+        if config.mirror_layout {
+            layout = mirror_layout(layout, self.axes);
+            layouts = layouts
+                .into_iter()
+                .map(|(name, layout)| (name, mirror_layout(layout, self.axes)))
+                .collect();
+        }
+        let missing_keys = missing_keys(
+            &keys_to_enable(
+                std::iter::once(&layout).chain(layouts.iter().map(|(_, layout)| layout)),
+                &config,
+            ),
+            &self.keys,
+        );
+        if !missing_keys.is_empty() {
+            warn!(
+                ?missing_keys,
+                "the new config uses keys which can only be sent after a restart"
+            );
+        }
+
+        self.stop_holding_key()?;
+        self.modifiers.clear();
+        if self.state.phase == Phase::Idle {
+            self.grab(GrabMode::Ungrab)?;
+        } else {
+            // the finger is on whatever was under it in the old layout
+            self.ignore_touch()?;
+        }
+        // stay on the same layout if it's still there
+        let layout_name = std::mem::take(&mut self.layouts[self.layout_index].0);
+        self.layouts = vec![(MAIN_LAYOUT_NAME.to_string(), layout)];
+        // so that the statuses sent while the layouts are added are consistent
+        self.layout_index = 0;
+        for (name, layout) in layouts {
+            self.add_layout(name, layout);
+        }
+        self.layout_index = self
+            .layouts
+            .iter()
+            .position(|(name, _)| *name == layout_name)
+            .unwrap_or(0);
+        if config.haptic_strength != self.config.haptic_strength {
+            // removing the old effect first frees its slot for the new one
+            self.haptics = None;
+            self.haptics = config
+                .haptic_strength
+                .and_then(|strength| Haptics::new(&self.touchpad, strength));
+        }
+        let light_always_on = config.light_always_on;
+        self.apply_config(config);
+        self.set_light_always_on(light_always_on)?;
+        self.notify_status();
+        info!("reloaded the config");
+        Ok(())
+    }
+
+    /// Run [NumberPad::enter_input_loop] on its own thread, which stops when [NumpadHandle::shutdown] is
+    /// called; signals are left to the rest of the application.
+    pub fn spawn(mut self) -> std::io::Result<NumpadHandle> {
+        let controller = self.controller()?;
+        self.handles_signals = false;
+        let thread = std::thread::Builder::new()
+            .name("numpad".to_string())
+            .spawn(move || self.enter_input_loop())?;
+        Ok(NumpadHandle { controller, thread })
+    }
+
+    /// Wait for touchpad events and handle them, until SIGTERM or SIGINT is received or a [Command::Shutdown].
+    /// SIGHUP reloads the config, see [NumberPad::reload].
+    /// Returns an error if waiting for events fails, in which case the touchpad is released just as it
+    /// would be on a signal, so the caller may call this again or give up.
+    pub fn enter_input_loop(&mut self) -> std::io::Result<()> {
+        let result = self.run_event_loop(Self::handle_event_or_warn);
+        let _ = self.stop_holding_key();
+        self.release();
+        result
+    }
+
+    fn handle_event_or_warn(&mut self, event: InputEvent) {
+        if let Err(error) = self.handle_touchpad_event(event) {
+            // a transient failure (e.g. the i2c bus being busy) isn't worth dying over
+            warn!("error while handling a touchpad event: {}", error);
+        }
+    }
+
+    fn reload_or_warn(&mut self) {
+        info!("received SIGHUP, reloading the config");
+        if let Err(error) = self.reload() {
+            warn!("couldn't reload the config, keeping the old one: {}", error);
+        }
+    }
+
+    /// Handle all the events which arrived since the last call; returns whether the touchpad is gone.
+    fn read_events(&mut self, handle_event: &impl Fn(&mut Self, InputEvent)) -> bool {
+        loop {
+            match self.touchpad.next_event(ReadFlag::NORMAL) {
+                // the kernel's buffer overflowed, so some events never made it to us
+                Ok((ReadStatus::Sync, _)) => {
+                    if self.resync() {
+                        return true;
+                    }
+                }
+                Ok((ReadStatus::Success, event)) => {
+                    if self.trace_raw {
+                        println!(
+                            "{}.{:06} {} {}",
+                            event.time.tv_sec, event.time.tv_usec, event.event_code, event.value
+                        );
+                    }
+                    handle_event(self, event)
+                }
+                Err(error) => return error.raw_os_error() == Some(ENODEV),
+            }
+        }
+    }
+
+    /// Catch up with the touchpad after a SYN_DROPPED: let libevdev read its current state, take the
+    /// contacts from that, and ignore the touch which was going on, since what happened during it is
+    /// unknown. Returns whether the touchpad is gone.
+    fn resync(&mut self) -> bool {
+        warn!("events from the touchpad were dropped, resynchronizing");
+        // the sync events describe how the state changed, which libevdev already applied to its own
+        loop {
+            match self.touchpad.next_event(ReadFlag::SYNC) {
+                Ok(_) => (),
+                // EAGAIN means it's in sync again
+                Err(error) => {
+                    if error.raw_os_error() == Some(ENODEV) {
+                        return true;
+                    }
+                    break;
+                }
+            }
+        }
+        if let Err(error) = self.stop_holding_key() {
+            warn!("couldn't release the held key: {}", error);
+        }
+        self.state.resync_contacts(&self.touchpad);
+        self.state.two_finger_tap = None;
+        self.is_grab_deferred = false;
+        // a finger which is down may have touched, moved or been held for any time meanwhile
+        self.state.phase = if self.state.contact_count == 0 {
+            Phase::Idle
+        } else {
+            Phase::Dragging
+        };
+        false
+    }
+
+    /// Print every touchpad event along with the key under the finger, without grabbing the touchpad
+    /// or pressing anything, until SIGTERM or SIGINT is received. Useful for calibrating layouts.
+    pub fn dump_events(&mut self) -> std::io::Result<()> {
+        self.run_event_loop(|number_pad, event| {
+            number_pad.state.track_contacts(&event);
+            let slot = number_pad.state.slot;
+            let Some(contact) = number_pad.state.contacts.get(slot).copied() else {
+                println!(
+                    "{} {} in untracked slot {}",
+                    event.event_code, event.value, slot
+                );
+                return;
+            };
+            println!(
+                "{} {} in slot {} at ({}, {}) -> {:?}",
+                event.event_code,
+                event.value,
+                slot,
+                contact.pos_x,
+                contact.pos_y,
+                number_pad.key_at(contact.pos_x, contact.pos_y)
+            );
+        })
+    }
+
+    /// Ask for taps on the top left and the bottom right corners of the numpad, as it's printed on the
+    /// touchpad, and fit the main layout between them; see [Layout::fit_to]. Two corners only move and
+    /// stretch the layout as a whole, so keys which are printed out of line with the rest, or a numpad
+    /// which isn't square with the touchpad, still need the layout file to be edited by hand.
+    /// Like [NumberPad::dump_events], this doesn't grab the touchpad or press anything.
+    pub fn calibrate(&mut self) -> std::io::Result<Layout<Action>> {
+        let layout = self.layouts[0].1.clone();
+        let rows = layout.rows();
+        let (Some(first), Some(last)) = (
+            rows.first().and_then(|row| row.items().first()),
+            rows.last().and_then(|row| row.items().last()),
+        ) else {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "the layout has no items to calibrate",
+            ));
+        };
+        println!(
+            "Tap the top left corner of the numpad, i.e. of its {} key",
+            first.item()
+        );
+        let (left, top) = self.wait_for_tap()?;
+        println!(
+            "Tap the bottom right corner of the numpad, i.e. of its {} key",
+            last.item()
+        );
+        let (right, bottom) = self.wait_for_tap()?;
+        info!(left, top, right, bottom, "calibrated the numpad's bounds");
+        if right <= left || bottom <= top {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "the bottom right corner has to be below and to the right of the top left one",
+            ));
+        }
+        Ok(layout.fit_to(Rect {
+            left,
+            top,
+            right,
+            bottom,
+        }))
+    }
+
+    /// Wait for a finger to touch the touchpad and be lifted again, returning where it was lifted.
+    fn wait_for_tap(&mut self) -> std::io::Result<(usize, usize)> {
+        loop {
+            let mut touchpad_fd = pollfd {
+                fd: self.touchpad.file().as_raw_fd(),
+                events: POLLIN,
+                revents: 0,
+            };
+            if unsafe { libc::poll(&mut touchpad_fd, 1, -1) } < 0 {
+                let error = std::io::Error::last_os_error();
+                if error.kind() == ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(error);
+            }
+            if touchpad_fd.revents & (POLLERR | POLLHUP | POLLNVAL) != 0 {
+                return Err(std::io::Error::from_raw_os_error(ENODEV));
+            }
+            while let Ok((_read_flags, event)) = self.touchpad.next_event(ReadFlag::NORMAL) {
+                self.state.track_contacts(&event);
+                if event.event_code != EventCode::EV_SYN(EV_SYN::SYN_REPORT) {
+                    continue;
+                }
+                if let Some(contact) = self.state.touch_slot.map(|slot| self.state.contacts[slot]) {
+                    self.state.pos_x = contact.pos_x;
+                    self.state.pos_y = contact.pos_y;
+                }
+                let contact_count = self.state.count_contacts();
+                let previous_count =
+                    std::mem::replace(&mut self.state.contact_count, contact_count);
+                if previous_count > 0 && contact_count == 0 {
+                    self.state.touch_slot = None;
+                    return Ok((self.state.pos_x, self.state.pos_y));
+                }
+            }
+        }
+    }
+
+    /// Call `handle_event` for every touchpad event until SIGTERM or SIGINT is received.
+    fn run_event_loop(
+        &mut self,
+        handle_event: impl Fn(&mut Self, InputEvent),
+    ) -> std::io::Result<()> {
+        self.is_shutting_down = false;
+        // signals are written into this socket so that they wake up the poll below
+        let (signal_receiver, signal_sender) = UnixStream::pair()?;
+        signal_receiver.set_nonblocking(true)?;
+        // SIGHUP asks for reloading the config rather than stopping, so it gets its own socket
+        let (reload_receiver, reload_sender) = UnixStream::pair()?;
+        reload_receiver.set_nonblocking(true)?;
+        let mut signal_ids = Vec::new();
+        if self.handles_signals {
+            for signal in [SIGTERM, SIGINT] {
+                signal_ids.push(signal_hook::low_level::pipe::register(
+                    signal,
+                    signal_sender.try_clone()?,
+                )?);
+            }
+            signal_ids.push(signal_hook::low_level::pipe::register(
+                SIGHUP,
+                reload_sender,
+            )?);
+        }
+
+        let mut fds = [
+            pollfd {
+                fd: self.touchpad.file().as_raw_fd(),
+                events: POLLIN,
+                revents: 0,
+            },
+            pollfd {
+                fd: signal_receiver.as_raw_fd(),
+                events: POLLIN,
+                revents: 0,
+            },
+            pollfd {
+                // poll ignores negative fds, so this is just skipped if there are no controllers
+                fd: self
+                    .commands
+                    .as_ref()
+                    .map_or(-1, |commands| commands.wake_receiver.as_raw_fd()),
+                events: POLLIN,
+                revents: 0,
+            },
+            pollfd {
+                fd: reload_receiver.as_raw_fd(),
+                events: POLLIN,
+                revents: 0,
+            },
+        ];
+        let result = loop {
+            // wait for some event to happen (or for the next timer) so that we don't busywait2
+            let timeout = self.next_timer().map_or(-1, |timeout| {
+                timeout.as_micros().div_ceil(1000).min(i32::MAX as u128) as i32
+            });
+            let ready = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) };
+            if ready < 0 {
+                let error = std::io::Error::last_os_error();
+                // a signal interrupted us; if it's one we care about, the socket will be readable
+                if error.kind() == ErrorKind::Interrupted {
+                    continue;
+                }
+                break Err(error);
+            }
+            if fds[1].revents & POLLIN != 0 {
+                // we were asked to stop
+                info!("received a signal, shutting down");
+                break Ok(());
+            }
+
+            // read all the events that happened
+            let mut is_touchpad_gone = fds[0].revents & (POLLERR | POLLHUP | POLLNVAL) != 0;
+            is_touchpad_gone |= self.read_events(&handle_event);
+            if is_touchpad_gone {
+                // e.g. the driver was reloaded or it didn't come back properly after a suspend
+                warn!("the touchpad disappeared");
+                match self.wait_for_touchpad(&signal_receiver) {
+                    Ok(true) => fds[0].fd = self.touchpad.file().as_raw_fd(),
+                    Ok(false) => {
+                        info!("received a signal, shutting down");
+                        break Ok(());
+                    }
+                    Err(error) => break Err(error),
+                }
+                continue;
+            }
+            if fds[2].revents & POLLIN != 0 {
+                self.handle_commands();
+                if self.is_shutting_down {
+                    info!("asked to shut down");
+                    break Ok(());
+                }
+            }
+            if fds[3].revents & POLLIN != 0 {
+                // several signals may have arrived since the last time, but one reload covers them all
+                let mut buffer = [0; 64];
+                while matches!((&reload_receiver).read(&mut buffer), Ok(1..)) {}
+                self.reload_or_warn();
+            }
+            if let Err(error) = self.handle_timers() {
+                warn!("error while handling timers: {}", error);
+            }
+        };
+
+        for id in signal_ids {
+            signal_hook::low_level::unregister(id);
+        }
+        result
+    }
+
+    /// Try to find and open the touchpad again until it works, backing off between attempts.
+    /// Returns false if we were asked to stop while waiting.
+    fn wait_for_touchpad(&mut self, signal_receiver: &UnixStream) -> std::io::Result<bool> {
+        let _ = self.stop_holding_key();
+        let mut delay = MIN_RECONNECT_DELAY;
+        let mut attempt = 0;
+        loop {
+            let mut signal_fd = pollfd {
+                fd: signal_receiver.as_raw_fd(),
+                events: POLLIN,
+                revents: 0,
+            };
+            let ready = unsafe { libc::poll(&mut signal_fd, 1, delay.as_millis() as i32) };
+            if ready < 0 {
+                let error = std::io::Error::last_os_error();
+                if error.kind() == ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(error);
+            }
+            if signal_fd.revents & POLLIN != 0 {
+                return Ok(false);
+            }
+            attempt += 1;
+            info!(attempt, "trying to find the touchpad again");
+            match self.reopen_touchpad() {
+                Ok(()) => return Ok(true),
+                Err(error) => {
+                    warn!("couldn't reopen the touchpad: {}", error);
+                    delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+                }
+            }
+        }
+    }
+
+    /// Open the touchpad (and the light, which may have moved along with it) again,
+    /// picking up where we left off.
+    fn reopen_touchpad(&mut self) -> Result<(), Error> {
+        let (touchpad, ids) = open_touchpad(&self.config.touchpad_name)?;
+        if self.light_controller.is_some() {
+            let light_controller = open_light(ids.i2c_id, &self.config)?;
+            let is_lit = self.is_lit();
+            // the old connection turns the light off as it's dropped, so it has to go before the light
+            // is set up again
+            let light_controller = self.light_controller.insert(light_controller);
+            if is_lit {
+                light_controller.turn_on()
+            } else {
+                light_controller.turn_off()
+            }
+            .map_err(Error::CouldntControlLight)?;
+            light_controller
+                .set_brightness(self.brightness)
+                .map_err(Error::CouldntControlLight)?;
+            self.flash_end = None;
+            self.blink = None;
+            self.state.is_dimmed = false;
+        }
+        self.axes = AxisRange::of(&touchpad, EV_ABS::ABS_MT_POSITION_X)
+            .zip(AxisRange::of(&touchpad, EV_ABS::ABS_MT_POSITION_Y));
+        self.touch_major_range = AxisRange::of(&touchpad, EV_ABS::ABS_MT_TOUCH_MAJOR);
+        // the effect was uploaded to the old device
+        self.haptics = None;
+        self.haptics = self
+            .config
+            .haptic_strength
+            .and_then(|strength| Haptics::new(&touchpad, strength));
+        // whatever touch was going on is lost; we only grab while touching, so there's no grab to restore
+        let mut state = NumpadState::new();
+        state.is_active = self.state.is_active;
+        state.slot = touchpad.current_slot().unwrap_or(0) as usize;
+        self.state = state;
+        self.touchpad = touchpad;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl NumberPad {
+    /// Like [NumberPad::enter_input_loop], but waits for the events on the tokio runtime this is run on,
+    /// so that an application which has one doesn't need a thread for the number pad.
+    /// The runtime needs to have IO and time enabled.
+    pub async fn enter_async_input_loop(&mut self) -> std::io::Result<()> {
+        let result = self.run_async_event_loop().await;
+        let _ = self.stop_holding_key();
+        self.release();
+        result
+    }
+
+    async fn run_async_event_loop(&mut self) -> std::io::Result<()> {
+        use tokio::{
+            io::{Interest, unix::AsyncFd},
+            signal::unix::{SignalKind, signal},
+        };
+
+        self.is_shutting_down = false;
+        let mut terminate = signal(SignalKind::terminate())?;
+        let mut interrupt = signal(SignalKind::interrupt())?;
+        let mut hangup = signal(SignalKind::hangup())?;
+        let command_waker = match &self.commands {
+            Some(commands) => Some(AsyncFd::with_interest(
+                commands.wake_receiver.as_raw_fd(),
+                Interest::READABLE,
+            )?),
+            None => None,
+        };
+        loop {
+            // the touchpad has to be registered again whenever it's reopened
+            let touchpad =
+                AsyncFd::with_interest(self.touchpad.file().as_raw_fd(), Interest::READABLE)?;
+            loop {
+                let timer = self.next_timer();
+                let mut is_touchpad_gone = false;
+                tokio::select! {
+                    _ = terminate.recv() => {
+                        info!("received a signal, shutting down");
+                        return Ok(());
+                    }
+                    _ = interrupt.recv() => {
+                        info!("received a signal, shutting down");
+                        return Ok(());
+                    }
+                    _ = hangup.recv() => self.reload_or_warn(),
+                    ready = touchpad.readable() => {
+                        // everything is read below, so whatever made it readable is dealt with
+                        ready?.clear_ready();
+                        is_touchpad_gone = self.read_events(&Self::handle_event_or_warn);
+                    }
+                    ready = readable(command_waker.as_ref()) => {
+                        ready?.clear_ready();
+                        self.handle_commands();
+                        if self.is_shutting_down {
+                            info!("asked to shut down");
+                            return Ok(());
+                        }
+                    }
+                    _ = sleep(timer) => (),
+                }
+                if is_touchpad_gone {
+                    break;
+                }
+                if let Err(error) = self.handle_timers() {
+                    warn!("error while handling timers: {}", error);
+                }
+            }
+            drop(touchpad);
+            warn!("the touchpad disappeared");
+            if !self
+                .wait_for_touchpad_async(&mut terminate, &mut interrupt)
+                .await
+            {
+                info!("received a signal, shutting down");
+                return Ok(());
+            }
+        }
+    }
+
+    /// Like [NumberPad::wait_for_touchpad], but with tokio's signal streams.
+    async fn wait_for_touchpad_async(
+        &mut self,
+        terminate: &mut tokio::signal::unix::Signal,
+        interrupt: &mut tokio::signal::unix::Signal,
+    ) -> bool {
+        let _ = self.stop_holding_key();
+        let mut delay = MIN_RECONNECT_DELAY;
+        let mut attempt = 0;
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => (),
+                _ = terminate.recv() => return false,
+                _ = interrupt.recv() => return false,
+            }
+            attempt += 1;
+            info!(attempt, "trying to find the touchpad again");
+            match self.reopen_touchpad() {
+                Ok(()) => return true,
+                Err(error) => {
+                    warn!("couldn't reopen the touchpad: {}", error);
+                    delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+                }
+            }
+        }
+    }
+}
+
+/// Wait until the fd is readable, or forever if there is none.
+#[cfg(feature = "tokio")]
+async fn readable(
+    fd: Option<&tokio::io::unix::AsyncFd<std::os::fd::RawFd>>,
+) -> std::io::Result<tokio::io::unix::AsyncFdReadyGuard<'_, std::os::fd::RawFd>> {
+    match fd {
+        Some(fd) => fd.readable().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Wait for the given time, or forever if it's None.
+#[cfg(feature = "tokio")]
+async fn sleep(duration: Option<Duration>) {
+    match duration {
+        Some(duration) => tokio::time::sleep(duration).await,
+        None => std::future::pending().await,
+    }
+}
+
+impl<T: GrabControl, K: KeyOutput, L: LightOutput> Drop for NumberPad<T, K, L> {
+    fn drop(&mut self) {
+        // make sure we don't leave the touchpad unusable or the light on once we're gone
+        self.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use evdev_rs::{TimeVal, enums::EV_MSC};
+
+    use super::*;
+    use crate::{
+        numpad_light::{LightModel, RecordingTransport},
+        output::fake::{FakeKeys, FakeLight, FakeTouchpad, KeyEvent},
+    };
+
+    type FakeNumberPad = NumberPad<FakeTouchpad, FakeKeys, FakeLight>;
+
+    /// Two rows of two 100x100 items: 1 and 2 on top, the activation key and 3 below.
+    fn number_pad(config: Config) -> FakeNumberPad {
+        let layout = Layout::grid(
+            &[
+                &[
+                    Some(Action::Key(EV_KEY::KEY_KP1)),
+                    Some(Action::Key(EV_KEY::KEY_KP2)),
+                ],
+                &[
+                    Some(Action::ToggleActive),
+                    Some(Action::Key(EV_KEY::KEY_KP3)),
+                ],
+            ],
+            Rect {
+                left: 0,
+                top: 0,
+                right: 199,
+                bottom: 199,
+            },
+            0,
+        );
+        let mut number_pad = NumberPad::from_parts(
+            FakeTouchpad::default(),
+            FakeKeys::default(),
+            Some(FakeLight::default()),
+            layout,
+            config,
+        );
+        number_pad.set_active(true).unwrap();
+        number_pad
+    }
+
+    fn send(number_pad: &mut FakeNumberPad, code: EventCode, value: i32) {
+        let event = InputEvent::new(&TimeVal::new(0, 0), &code, value);
+        number_pad.handle_touchpad_event(event).unwrap();
+    }
+
+    /// Put a finger into the slot, or move the one which is in it.
+    fn finger(number_pad: &mut FakeNumberPad, slot: i32, x: i32, y: i32) {
+        send(number_pad, EventCode::EV_ABS(EV_ABS::ABS_MT_SLOT), slot);
+        if number_pad.state.contacts[slot as usize]
+            .tracking_id
+            .is_none()
+        {
+            send(
+                number_pad,
+                EventCode::EV_ABS(EV_ABS::ABS_MT_TRACKING_ID),
+                slot + 1,
+            );
+        }
+        send(number_pad, EventCode::EV_ABS(EV_ABS::ABS_MT_POSITION_X), x);
+        send(number_pad, EventCode::EV_ABS(EV_ABS::ABS_MT_POSITION_Y), y);
+    }
+
+    fn lift_finger(number_pad: &mut FakeNumberPad, slot: i32) {
+        send(number_pad, EventCode::EV_ABS(EV_ABS::ABS_MT_SLOT), slot);
+        send(
+            number_pad,
+            EventCode::EV_ABS(EV_ABS::ABS_MT_TRACKING_ID),
+            -1,
+        );
+    }
+
+    /// End the frame as the touchpad does, with a timestamp and a report.
+    fn frame(number_pad: &mut FakeNumberPad) {
+        send(number_pad, EventCode::EV_MSC(EV_MSC::MSC_TIMESTAMP), 0);
+        send(number_pad, EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0);
+    }
+
+    #[test]
+    fn missing_keys_are_the_ones_not_enabled() {
+        let keys = [
+            EV_KEY::KEY_KP1,
+            EV_KEY::KEY_F13,
+            EV_KEY::KEY_KP2,
+            EV_KEY::KEY_F14,
+        ];
+        let enabled = [EV_KEY::KEY_KP2, EV_KEY::KEY_KP1];
+        assert_eq!(
+            missing_keys(&keys, &enabled),
+            [EV_KEY::KEY_F13, EV_KEY::KEY_F14]
+        );
+        assert_eq!(missing_keys(&enabled, &keys), []);
+    }
+
+    #[test]
+    fn layout_files_are_named_after_their_stem() {
+        assert_eq!(
+            layout_name(Path::new("/layouts/nav.toml")).as_deref(),
+            Some("nav")
+        );
+        assert_eq!(layout_name(Path::new("a.b.toml")).as_deref(), Some("a.b"));
+        for path in [
+            "/layouts/.toml",
+            "/layouts/notes.txt",
+            "/layouts/toml",
+            "/",
+            "..",
+        ] {
+            assert_eq!(layout_name(Path::new(path)), None, "{}", path);
+        }
+    }
+
+    #[test]
+    fn the_brightness_is_read_from_the_light_if_it_can_tell() {
+        let mut light =
+            NumpadLight::with_transport(RecordingTransport::default(), LightModel::default_model());
+        // the light is off, so it doesn't answer with a brightness
+        assert_eq!(current_brightness(&mut light, 4), 4);
+        light.transport_mut().answer = vec![0x07, 0x00, 0x0d, 0x14, 0x03, 2 + 65, 0xad];
+        assert_eq!(current_brightness(&mut light, 4), 2);
+    }
+
+    #[test]
+    fn subscribers_get_the_layouts_added_later() {
+        let mut number_pad = number_pad(Config::default());
+        let statuses = number_pad.subscribe();
+        number_pad.add_layout("nav", Layout::new(Vec::new()));
+        let status = statuses.try_recv().unwrap();
+        assert_eq!(status.layout_names, [MAIN_LAYOUT_NAME, "nav"]);
+        assert_eq!(status.layout, 0);
+        assert_eq!(status.max_brightness, MAX_BRIGHTNESS);
+
+        // replacing a layout doesn't change the names
+        number_pad.add_layout("nav", Layout::new(Vec::new()));
+        assert!(statuses.try_recv().is_err());
+    }
+
+    #[test]
+    fn tap_presses_the_key() {
+        let mut number_pad = number_pad(Config::default());
+        finger(&mut number_pad, 0, 50, 50);
+        frame(&mut number_pad);
+        lift_finger(&mut number_pad, 0);
+        frame(&mut number_pad);
+
+        assert_eq!(
+            number_pad.key_simulator.events,
+            [KeyEvent::Press(vec![EV_KEY::KEY_KP1])]
+        );
+        assert_eq!(number_pad.touchpad.grabs, [true]);
+        assert_eq!(number_pad.stats().key_presses, [(EV_KEY::KEY_KP1, 1)]);
+    }
+
+    #[test]
+    fn tap_does_nothing_while_inactive() {
+        let mut number_pad = number_pad(Config::default());
+        number_pad.set_active(false).unwrap();
+        number_pad.touchpad.grabs.clear();
+        finger(&mut number_pad, 0, 50, 50);
+        frame(&mut number_pad);
+        lift_finger(&mut number_pad, 0);
+        frame(&mut number_pad);
+
+        assert_eq!(number_pad.key_simulator.events, []);
+        assert_eq!(number_pad.touchpad.grabs, []);
+    }
+
+    #[test]
+    fn hybrid_grab_leaves_quick_taps_ungrabbed() {
+        let mut number_pad = number_pad(Config {
+            hybrid_grab_delay: Some(Duration::from_secs(1)),
+            ..Config::default()
+        });
+        finger(&mut number_pad, 0, 50, 50);
+        frame(&mut number_pad);
+        assert_eq!(number_pad.touchpad.grabs, []);
+        lift_finger(&mut number_pad, 0);
+        frame(&mut number_pad);
+
+        // the key is still pressed, but the touchpad's driver got the tap too
+        assert_eq!(
+            number_pad.key_simulator.events,
+            [KeyEvent::Press(vec![EV_KEY::KEY_KP1])]
+        );
+        assert_eq!(number_pad.touchpad.grabs, [false]);
+    }
+
+    #[test]
+    fn hybrid_grab_grabs_slow_taps_after_the_delay() {
+        let mut number_pad = number_pad(Config {
+            hybrid_grab_delay: Some(Duration::from_millis(10)),
+            ..Config::default()
+        });
+        finger(&mut number_pad, 0, 50, 50);
+        frame(&mut number_pad);
+        std::thread::sleep(Duration::from_millis(20));
+        frame(&mut number_pad);
+        assert_eq!(number_pad.touchpad.grabs, [true]);
+        lift_finger(&mut number_pad, 0);
+        frame(&mut number_pad);
+
+        assert_eq!(
+            number_pad.key_simulator.events,
+            [KeyEvent::Press(vec![EV_KEY::KEY_KP1])]
+        );
+        assert_eq!(number_pad.touchpad.grabs, [true, false]);
+    }
+
+    #[test]
+    fn hold_holds_the_key_until_lifted() {
+        let mut number_pad = number_pad(Config {
+            hold_duration: Duration::from_millis(10),
+            ..Config::default()
+        });
+        finger(&mut number_pad, 0, 150, 50);
+        frame(&mut number_pad);
+        std::thread::sleep(Duration::from_millis(20));
+        frame(&mut number_pad);
+        assert_eq!(number_pad.holding_key(), Some(EV_KEY::KEY_KP2));
+        lift_finger(&mut number_pad, 0);
+        frame(&mut number_pad);
+
+        assert_eq!(
+            number_pad.key_simulator.events,
+            [
+                KeyEvent::Down(vec![EV_KEY::KEY_KP2]),
+                KeyEvent::Up(vec![EV_KEY::KEY_KP2]),
+            ]
+        );
+        assert_eq!(number_pad.holding_key(), None);
+    }
+
+    #[test]
+    fn hold_on_a_border_stays_on_the_touched_key() {
+        let mut number_pad = number_pad(Config {
+            hold_duration: Duration::from_millis(10),
+            ..Config::default()
+        });
+        // 1 ends at x = 99, and the finger jitters across that
+        finger(&mut number_pad, 0, 97, 50);
+        frame(&mut number_pad);
+        for x in [103, 96, 105, 101] {
+            finger(&mut number_pad, 0, x, 50);
+            frame(&mut number_pad);
+        }
+        std::thread::sleep(Duration::from_millis(20));
+        frame(&mut number_pad);
+
+        assert_eq!(number_pad.holding_key(), Some(EV_KEY::KEY_KP1));
+    }
+
+    #[test]
+    fn hold_follows_the_finger_past_the_hysteresis() {
+        let mut number_pad = number_pad(Config {
+            hold_duration: Duration::from_millis(10),
+            ..Config::default()
+        });
+        // short of a drag, but far enough onto 2 to be on it
+        finger(&mut number_pad, 0, 90, 50);
+        frame(&mut number_pad);
+        finger(&mut number_pad, 0, 115, 50);
+        frame(&mut number_pad);
+        std::thread::sleep(Duration::from_millis(20));
+        frame(&mut number_pad);
+
+        assert_eq!(number_pad.holding_key(), Some(EV_KEY::KEY_KP2));
+        assert_eq!(
+            number_pad.key_simulator.events,
+            [KeyEvent::Down(vec![EV_KEY::KEY_KP2])]
+        );
+    }
+
+    #[test]
+    fn drag_lets_the_pointer_move() {
+        let mut number_pad = number_pad(Config::default());
+        finger(&mut number_pad, 0, 50, 20);
+        frame(&mut number_pad);
+        finger(&mut number_pad, 0, 50, 80);
+        frame(&mut number_pad);
+        frame(&mut number_pad);
+        lift_finger(&mut number_pad, 0);
+        frame(&mut number_pad);
+
+        assert_eq!(number_pad.key_simulator.events, []);
+        assert_eq!(number_pad.touchpad.grabs, [true, false]);
+    }
+
+    #[test]
+    fn drag_from_the_activation_key_changes_the_brightness() {
+        let mut number_pad = number_pad(Config::default());
+        assert_eq!(number_pad.brightness(), MAX_BRIGHTNESS);
+        finger(&mut number_pad, 0, 50, 120);
+        frame(&mut number_pad);
+        finger(&mut number_pad, 0, 50, 180);
+        frame(&mut number_pad);
+        frame(&mut number_pad);
+        lift_finger(&mut number_pad, 0);
+        frame(&mut number_pad);
+
+        // dragging down dims it by a level, and the numpad stays active
+        assert_eq!(number_pad.brightness(), MAX_BRIGHTNESS - 1);
+        assert_eq!(
+            number_pad.light_status().map(|status| status.level),
+            Some(MAX_BRIGHTNESS - 1)
+        );
+        assert!(number_pad.is_active());
+        assert_eq!(number_pad.key_simulator.events, []);
+        assert_eq!(number_pad.touchpad.grabs, [true, true]);
+    }
+
+    #[test]
+    fn contacts_are_tracked_per_slot() {
+        let mut number_pad = number_pad(Config::default());
+        let abs = |code| EventCode::EV_ABS(code);
+        for (code, value) in [
+            (EV_ABS::ABS_MT_SLOT, 0),
+            (EV_ABS::ABS_MT_TRACKING_ID, 10),
+            (EV_ABS::ABS_MT_POSITION_X, 50),
+            (EV_ABS::ABS_MT_POSITION_Y, 50),
+            (EV_ABS::ABS_MT_SLOT, 1),
+            (EV_ABS::ABS_MT_TRACKING_ID, 11),
+            (EV_ABS::ABS_MT_POSITION_X, 150),
+            (EV_ABS::ABS_MT_POSITION_Y, 160),
+            // back to the first finger, which only moves along x
+            (EV_ABS::ABS_MT_SLOT, 0),
+            (EV_ABS::ABS_MT_POSITION_X, 60),
+        ] {
+            send(&mut number_pad, abs(code), value);
+        }
+        let contacts = &number_pad.state.contacts;
+        assert_eq!(
+            [contacts[0].tracking_id, contacts[1].tracking_id],
+            [Some(10), Some(11)]
+        );
+        assert_eq!((contacts[0].pos_x, contacts[0].pos_y), (60, 50));
+        assert_eq!((contacts[1].pos_x, contacts[1].pos_y), (150, 160));
+
+        send(&mut number_pad, abs(EV_ABS::ABS_MT_SLOT), 1);
+        send(&mut number_pad, abs(EV_ABS::ABS_MT_TRACKING_ID), -1);
+        let contacts = &number_pad.state.contacts;
+        assert_eq!(
+            [contacts[0].tracking_id, contacts[1].tracking_id],
+            [Some(10), None]
+        );
+        assert_eq!(number_pad.state.slot, 1);
+    }
+
+    #[test]
+    fn two_finger_tap_right_clicks() {
+        let mut number_pad = number_pad(Config {
+            two_finger_right_click: true,
+            ..Config::default()
+        });
+        finger(&mut number_pad, 0, 50, 50);
+        frame(&mut number_pad);
+        finger(&mut number_pad, 1, 150, 150);
+        frame(&mut number_pad);
+
+        // both fingers are tracked, but only the first one is followed
+        assert_eq!(number_pad.state.contact_count, 2);
+        assert_eq!(number_pad.state.touch_slot, Some(0));
+        let contacts = &number_pad.state.contacts;
+        assert_eq!((contacts[0].pos_x, contacts[0].pos_y), (50, 50));
+        assert_eq!((contacts[1].pos_x, contacts[1].pos_y), (150, 150));
+        assert_eq!((number_pad.state.pos_x, number_pad.state.pos_y), (50, 50));
+
+        lift_finger(&mut number_pad, 1);
+        lift_finger(&mut number_pad, 0);
+        frame(&mut number_pad);
+
+        assert_eq!(number_pad.state.contact_count, 0);
+        assert_eq!(
+            number_pad.key_simulator.events,
+            [KeyEvent::Press(vec![EV_KEY::BTN_RIGHT])]
+        );
+        // the touch was let through as soon as the second finger came down
+        assert_eq!(number_pad.touchpad.grabs, [true, false]);
+    }
 }
@@ -0,0 +1,765 @@
+//! Following the fingers on the touchpad and deciding what their touches do: tapping, holding and dragging
+//! on the keys, the activation gestures, chords and two finger taps.
+
+use std::time::Instant;
+
+use evdev_rs::{
+    DeviceWrapper, GrabMode, InputEvent,
+    enums::{EV_ABS, EV_KEY, EV_MSC, EV_SYN, EventCode},
+};
+use tracing::{debug, info, trace, warn};
+
+use super::{Error, NumberPad};
+use crate::{
+    layout::{Action, RowItem},
+    output::{GrabControl, KeyOutput, LightOutput},
+};
+
+/// What the finger we're following is up to, which decides what lifting it does; see [NumberPad::lift].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Phase {
+    /// No finger is on the touchpad.
+    Idle,
+    /// The finger is on the touchpad, neither for long nor far from where it touched, so lifting it taps
+    /// whatever is under it.
+    Touching,
+    /// The finger has been on an item for longer than the hold duration, holding down the given key if
+    /// it's a key item; lifting it only releases whatever is held.
+    Holding(Option<EV_KEY>),
+    /// The finger moved as far as a drag, or the touch isn't meant for the numpad at all, e.g. it's a gesture;
+    /// lifting it does nothing.
+    Dragging,
+    /// The finger is dragging from the activation key, so lifting it changes the brightness.
+    BrightnessDrag,
+    /// The finger is dragging from the top right corner, see [Config::activation_swipe], so lifting it
+    /// toggles the numpad if it went down and to the left.
+    CornerSwipe,
+    /// The finger and a second one are on the two keys of the chord with the given index, holding down
+    /// its keys; lifting either finger releases them, and the rest of the touch does nothing.
+    Chord(usize),
+}
+
+#[derive(Debug)]
+pub(super) struct LastTouch {
+    pub(super) pos_x: usize,
+    pub(super) pos_y: usize,
+    pub(super) time: Instant,
+    pub(super) key: Option<Action>,
+    /// What holding the touched item does instead of holding down its key, if anything else.
+    pub(super) hold_key: Option<Action>,
+}
+/// The number of multitouch slots we keep track of; contacts in higher slots are ignored.
+pub(super) const MAX_CONTACTS: usize = 10;
+
+/// A multitouch slot, i.e. a finger as reported by the touchpad.
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct Contact {
+    /// The id the touchpad gave the finger in this slot, or None if there is no finger in it.
+    pub(super) tracking_id: Option<i32>,
+    // the touchpad only reports values which changed, so these are kept even when the finger is lifted
+    pub(super) pos_x: usize,
+    pub(super) pos_y: usize,
+    /// The length of the major axis of the contact area, which is large for palms.
+    pub(super) touch_major: usize,
+}
+
+#[derive(Debug)]
+pub(super) struct NumpadState {
+    /// The position of the finger we're following, i.e. the first one that touched,
+    /// as of the last frame. Kept after the finger is lifted.
+    pub(super) pos_x: usize,
+    pub(super) pos_y: usize,
+    /// The multitouch slot which the touchpad is currently reporting about.
+    pub(super) slot: usize,
+    pub(super) contacts: [Contact; MAX_CONTACTS],
+    /// The slot of the finger we're following, if any.
+    pub(super) touch_slot: Option<usize>,
+    /// How many fingers were on the touchpad as of the last frame.
+    pub(super) contact_count: usize,
+    pub(super) last_touch: LastTouch,
+    pub(super) is_active: bool,
+    pub(super) phase: Phase,
+    /// When a second finger joined the touch, if the touch may still turn out to be a two finger tap.
+    pub(super) two_finger_tap: Option<Instant>,
+    /// When the last touchpad event arrived, for turning off the light when the numpad is idle.
+    pub(super) last_event: Instant,
+    /// Whether the light was turned off because the numpad is idle.
+    pub(super) is_idle: bool,
+    /// Whether the light was dimmed because the numpad is about to be idle; the brightness it's restored
+    /// to is still the numpad's brightness.
+    pub(super) is_dimmed: bool,
+}
+
+impl NumpadState {
+    pub(super) fn new() -> Self {
+        Self {
+            pos_x: 0,
+            pos_y: 0,
+            slot: 0,
+            contacts: [Contact::default(); MAX_CONTACTS],
+            touch_slot: None,
+            contact_count: 0,
+            last_touch: LastTouch {
+                pos_x: 0,
+                pos_y: 0,
+                time: Instant::now(),
+                key: None,
+                hold_key: None,
+            },
+            is_active: false,
+            phase: Phase::Idle,
+            two_finger_tap: None,
+            last_event: Instant::now(),
+            is_idle: false,
+            is_dimmed: false,
+        }
+    }
+
+    /// Update the contacts from a multitouch event; the contacts are only consistent once the frame
+    /// ends with a SYN_REPORT.
+    pub(super) fn track_contacts(&mut self, event: &InputEvent) {
+        let contact = self.contacts.get_mut(self.slot);
+        match (&event.event_code, contact) {
+            (EventCode::EV_ABS(EV_ABS::ABS_MT_SLOT), _) => {
+                self.slot = event.value as usize;
+            }
+            (EventCode::EV_ABS(EV_ABS::ABS_MT_TRACKING_ID), Some(contact)) => {
+                // a tracking id of -1 means the finger in this slot was lifted
+                contact.tracking_id = Some(event.value).filter(|id| *id >= 0);
+                if contact.tracking_id.is_some() && self.touch_slot.is_none() {
+                    self.touch_slot = Some(self.slot);
+                }
+            }
+            (EventCode::EV_ABS(EV_ABS::ABS_MT_POSITION_X), Some(contact)) => {
+                contact.pos_x = event.value as usize;
+            }
+            (EventCode::EV_ABS(EV_ABS::ABS_MT_POSITION_Y), Some(contact)) => {
+                contact.pos_y = event.value as usize;
+            }
+            (EventCode::EV_ABS(EV_ABS::ABS_MT_TOUCH_MAJOR), Some(contact)) => {
+                contact.touch_major = event.value as usize;
+            }
+            _ => (),
+        }
+    }
+
+    /// Take the contacts from the touchpad's state as libevdev knows it, once it resynchronized after
+    /// events were dropped, since the events which would have kept them up to date are gone.
+    pub(super) fn resync_contacts(&mut self, touchpad: &impl DeviceWrapper) {
+        self.slot = touchpad.current_slot().map_or(0, |slot| slot as usize);
+        for (slot, contact) in self.contacts.iter_mut().enumerate() {
+            let value = |code| touchpad.slot_value(slot as u32, &EventCode::EV_ABS(code));
+            contact.tracking_id = value(EV_ABS::ABS_MT_TRACKING_ID).filter(|id| *id >= 0);
+            if let Some(x) = value(EV_ABS::ABS_MT_POSITION_X) {
+                contact.pos_x = x as usize;
+            }
+            if let Some(y) = value(EV_ABS::ABS_MT_POSITION_Y) {
+                contact.pos_y = y as usize;
+            }
+            if let Some(touch_major) = value(EV_ABS::ABS_MT_TOUCH_MAJOR) {
+                contact.touch_major = touch_major as usize;
+            }
+        }
+        // keep following the same finger if it's still there
+        self.touch_slot = self
+            .touch_slot
+            .filter(|slot| self.contacts[*slot].tracking_id.is_some())
+            .or_else(|| {
+                self.contacts
+                    .iter()
+                    .position(|contact| contact.tracking_id.is_some())
+            });
+        if let Some(slot) = self.touch_slot {
+            self.pos_x = self.contacts[slot].pos_x;
+            self.pos_y = self.contacts[slot].pos_y;
+        }
+        self.contact_count = self.count_contacts();
+    }
+
+    /// How many fingers are on the touchpad.
+    pub(super) fn count_contacts(&self) -> usize {
+        self.contacts
+            .iter()
+            .filter(|contact| contact.tracking_id.is_some())
+            .count()
+    }
+}
+
+impl<T: GrabControl, K: KeyOutput, L: LightOutput> NumberPad<T, K, L> {
+    /// Get the key at the given touchpad position, translating the position into the layout's
+    /// coordinates if the layout isn't in raw device coordinates.
+    fn item_at(&self, x: usize, y: usize) -> Option<&RowItem<Action>> {
+        match (self.layout().size(), self.axes) {
+            (Some((width, height)), Some((x_range, y_range))) => self.layout().get_row_item(
+                (x_range.normalize(x) * width as f64) as usize,
+                (y_range.normalize(y) * height as f64) as usize,
+            ),
+            _ => self.layout().get_row_item(x, y),
+        }
+    }
+
+    pub(super) fn key_at(&self, x: usize, y: usize) -> Option<&Action> {
+        self.item_at(x, y).map(RowItem::item)
+    }
+
+    /// The item the finger is on, given hysteresis: the position is taken as [Config::key_hysteresis]
+    /// back towards where the finger touched, so that it's still on the touched item until it went that
+    /// far past its edge, wherever it crossed it.
+    fn touched_item(&self) -> Option<&RowItem<Action>> {
+        let (from_x, from_y) = (
+            self.state.last_touch.pos_x as f64,
+            self.state.last_touch.pos_y as f64,
+        );
+        let (dx, dy) = (
+            self.state.pos_x as f64 - from_x,
+            self.state.pos_y as f64 - from_y,
+        );
+        let distance = dx.hypot(dy);
+        if distance <= self.config.key_hysteresis {
+            return self.item_at(self.state.last_touch.pos_x, self.state.last_touch.pos_y);
+        }
+        let progress = (distance - self.config.key_hysteresis) / distance;
+        self.item_at(
+            (from_x + dx * progress).round() as usize,
+            (from_y + dy * progress).round() as usize,
+        )
+    }
+
+    /// The key the finger is on, given hysteresis, see [NumberPad::touched_item].
+    fn touched_key(&self) -> Option<&Action> {
+        self.touched_item().map(RowItem::item)
+    }
+
+    /// The index of the chord the two fingers which are down make, if they're on the active numpad and
+    /// the second one touched soon enough after the first, see [Config::chord_window].
+    fn touched_chord(&self) -> Option<usize> {
+        if !self.state.is_active
+            || self.state.phase != Phase::Touching
+            || self.state.last_touch.time.elapsed() > self.config.chord_window
+        {
+            return None;
+        }
+        let Some(Action::Key(first)) = self.state.last_touch.key else {
+            return None;
+        };
+        let second = self
+            .state
+            .contacts
+            .iter()
+            .enumerate()
+            .find(|(slot, contact)| {
+                contact.tracking_id.is_some() && Some(*slot) != self.state.touch_slot
+            })
+            .and_then(|(_, contact)| self.key_at(contact.pos_x, contact.pos_y));
+        let Some(&Action::Key(second)) = second else {
+            return None;
+        };
+        self.config
+            .chords
+            .iter()
+            .position(|chord| chord.matches(first, second))
+    }
+
+    /// Hold down the keys of the chord until either finger is lifted.
+    fn start_chord(&mut self, index: usize) -> Result<(), Error> {
+        self.state.phase = Phase::Chord(index);
+        if std::mem::take(&mut self.is_grab_deferred) {
+            self.grab(GrabMode::Grab)?;
+        }
+        let keys = self.config.chords[index].press.clone();
+        debug!(?keys, "holding the chord");
+        if self.dry_run {
+            println!("would hold {:?}", keys);
+        }
+        self.with_keys(|output| output.keys_down(&keys))
+    }
+
+    fn is_drag_down(&self) -> bool {
+        self.state.pos_y > self.state.last_touch.pos_y
+    }
+
+    fn is_drag_up(&self) -> bool {
+        self.state.pos_y < self.state.last_touch.pos_y
+    }
+
+    /// All fingers were lifted; do whatever that means for the phase the touch was in, which ends it.
+    fn lift(&mut self) -> Result<(), Error> {
+        // the key needs to be released while the phase still says which one it is
+        self.stop_holding_key()?;
+        if self.dry_run {
+            let (x, y) = (self.state.pos_x, self.state.pos_y);
+            match self.touched_key() {
+                Some(action) => println!("lifted at ({}, {}) on {:?}", x, y, action),
+                None => println!("lifted at ({}, {}) outside of the layout", x, y),
+            }
+        }
+        self.is_grab_deferred = false;
+        match std::mem::replace(&mut self.state.phase, Phase::Idle) {
+            Phase::Idle | Phase::Dragging | Phase::Holding(_) | Phase::Chord(_) => (),
+            Phase::BrightnessDrag => self.end_brightness_drag()?,
+            Phase::CornerSwipe => self.end_corner_swipe()?,
+            Phase::Touching => self.tap()?,
+        }
+        if self.config.hybrid_grab_delay.is_some() {
+            // so that the next touch can move the pointer right away
+            self.grab(GrabMode::Ungrab)?;
+        }
+        Ok(())
+    }
+
+    /// A drag from the activation key ended; it changes the brightness if the numpad is active, by more
+    /// levels the further it went, see [Config::brightness_drag_step].
+    fn end_brightness_drag(&mut self) -> Result<(), Error> {
+        if self.state.is_active && self.config.live_brightness_drag {
+            // it changed as the finger moved
+            self.grab(GrabMode::Grab)?;
+        } else if self.state.is_active {
+            let distance = self.state.pos_y.abs_diff(self.state.last_touch.pos_y) as f64;
+            let levels = match self.config.brightness_drag_step {
+                Some(step) => ((distance / step) as i32).max(1),
+                None => 1,
+            };
+            let levels = if self.config.invert_brightness_drag {
+                -levels
+            } else {
+                levels
+            };
+            if self.is_drag_up() {
+                self.change_brightness(levels)?;
+            } else if self.is_drag_down() {
+                self.change_brightness(-levels)?;
+            }
+            // we didn't stop the grab if it started from
+            self.grab(GrabMode::Grab)?;
+        }
+        Ok(())
+    }
+
+    /// The finger moved during a drag from the activation key; change the brightness by a level for
+    /// every step it went up or down since the last time, see [Config::live_brightness_drag].
+    fn update_live_brightness_drag(&mut self) -> Result<(), Error> {
+        let step = self
+            .config
+            .brightness_drag_step
+            .unwrap_or(self.config.drag_distance)
+            .max(1.0);
+        let distance = self.state.last_touch.pos_y as f64 - self.state.pos_y as f64;
+        let levels = (distance / step) as i32;
+        let levels = if self.config.invert_brightness_drag {
+            -levels
+        } else {
+            levels
+        };
+        if levels != self.brightness_drag_levels {
+            let change = levels - self.brightness_drag_levels;
+            self.brightness_drag_levels = levels;
+            self.change_brightness(change)?;
+        }
+        Ok(())
+    }
+
+    /// Whether the position is in the corner which swiping from toggles the numpad, see
+    /// [Config::activation_swipe].
+    fn is_in_activation_corner(&self, x: usize, y: usize) -> bool {
+        match (self.config.activation_swipe, self.axes) {
+            (Some(size), Some((x_range, y_range))) => {
+                x_range.normalize(x) >= 1.0 - size && y_range.normalize(y) <= size
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the activation key was tapped twice in quick succession, if it needs to be, see
+    /// [Config::activation_double_tap]; otherwise, the time of the tap is kept for the next one.
+    fn is_second_activation_tap(&mut self) -> bool {
+        let Some(window) = self.config.activation_double_tap else {
+            return true;
+        };
+        match self.last_activation_tap.take() {
+            Some(time) if time.elapsed() <= window => true,
+            _ => {
+                self.last_activation_tap = Some(Instant::now());
+                false
+            }
+        }
+    }
+
+    /// Start [Config::activation_hold_command], without waiting for it to finish.
+    fn run_activation_hold_command(&self) {
+        let Some((program, args)) = self
+            .config
+            .activation_hold_command
+            .as_ref()
+            .and_then(|command| command.split_first())
+        else {
+            return;
+        };
+        info!(program, "the activation key was held, running the command");
+        match std::process::Command::new(program).args(args).spawn() {
+            Ok(mut child) => {
+                // reap it once it's done, so that it doesn't stay around as a zombie
+                std::thread::spawn(move || child.wait());
+            }
+            Err(error) => warn!(program, %error, "couldn't run the activation hold command"),
+        }
+    }
+
+    /// A swipe from the activation corner ended; it toggles the numpad if it went down and to the left,
+    /// roughly diagonally, rather than along the edges.
+    fn end_corner_swipe(&mut self) -> Result<(), Error> {
+        let left = self.state.last_touch.pos_x as f64 - self.state.pos_x as f64;
+        let down = self.state.pos_y as f64 - self.state.last_touch.pos_y as f64;
+        if left > 0.0 && down > 0.0 && left.min(down) * 2.0 >= left.max(down) {
+            self.set_active(!self.state.is_active)?;
+        }
+        Ok(())
+    }
+
+    /// Whether the action toggles the numpad, i.e. it's [Action::ToggleActive] or the activation key.
+    fn is_activation(&self, action: Action) -> bool {
+        action == Action::ToggleActive || action == self.config.activation_key
+    }
+
+    /// The finger was lifted where it touched before the hold duration; do what the item under it does.
+    fn tap(&mut self) -> Result<(), Error> {
+        if let Some(&action) = self.touched_key() {
+            let action = if self.is_activation(action) {
+                Action::ToggleActive
+            } else {
+                action
+            };
+            match action {
+                // it has to be held instead, see Config::activation_hold
+                Action::ToggleActive if self.config.activation_hold.is_some() => (),
+                Action::ToggleActive if !self.is_second_activation_tap() => {
+                    debug!("waiting for the activation key to be tapped again");
+                }
+                Action::ToggleActive => self.set_active(!self.state.is_active)?,
+                Action::BrightnessUp if self.state.is_active => self.step_brightness(true)?,
+                Action::BrightnessDown if self.state.is_active => self.step_brightness(false)?,
+                Action::Modifier(key) if self.state.is_active => self.tap_modifier(key),
+                Action::NextLayout if self.state.is_active => self.next_layout()?,
+                Action::SwitchLayout(index) if self.state.is_active => self.switch_layout(index)?,
+                Action::Unicode(character) if self.state.is_active => {
+                    self.type_unicode(character)?
+                }
+                Action::BrightnessUp
+                | Action::BrightnessDown
+                | Action::Modifier(_)
+                | Action::NextLayout
+                | Action::SwitchLayout(_)
+                | Action::DeadZone
+                | Action::Unicode(_) => (),
+                Action::Key(key) if self.is_bounce(key) => {
+                    debug!(
+                        ?key,
+                        "ignoring a tap right after the last one of the same key"
+                    );
+                }
+                Action::Key(key) => {
+                    if self.state.is_active {
+                        self.last_press = Some((key, Instant::now()));
+                        self.press(key)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The finger has been on an item for longer than the hold duration. Keys are held down until the
+    /// finger is lifted, any other hold item is done once; either way lifting the finger doesn't tap.
+    fn long_press(&mut self) -> Result<(), Error> {
+        let action = match (self.state.last_touch.hold_key, self.state.last_touch.key) {
+            (Some(hold_key), _) => hold_key,
+            // without a hold item, only keys do something when held; the other actions only happen on taps
+            (None, Some(Action::Key(key))) => Action::Key(key),
+            // holding it keeps it from toggling when the finger is lifted
+            (None, Some(action)) if self.is_activation(action) => action,
+            _ => return Ok(()),
+        };
+        self.state.phase = Phase::Holding(None);
+        let action = if self.is_activation(action) {
+            Action::ToggleActive
+        } else {
+            action
+        };
+        match action {
+            Action::ToggleActive => {
+                // holding the activation key itself does nothing, it's only a key to toggle with when
+                // it's another item's hold item
+                if self.state.last_touch.hold_key.is_some() {
+                    self.set_active(!self.state.is_active)?;
+                }
+            }
+            Action::BrightnessUp => self.step_brightness(true)?,
+            Action::BrightnessDown => self.step_brightness(false)?,
+            Action::Modifier(key) => self.tap_modifier(key),
+            Action::NextLayout => self.next_layout()?,
+            Action::SwitchLayout(index) => self.switch_layout(index)?,
+            Action::DeadZone => (),
+            // there's no key to hold down, so it's typed once
+            Action::Unicode(character) => self.type_unicode(character)?,
+            Action::Key(key) => {
+                debug!(?key, "holding key");
+                self.count_press(key);
+                self.state.phase = Phase::Holding(Some(key));
+                self.held_modifiers = self.take_modifiers();
+                let keys: Vec<EV_KEY> = self.held_modifiers.iter().copied().chain([key]).collect();
+                if self.dry_run {
+                    println!("would hold {:?}", keys);
+                }
+                self.with_keys(|output| output.keys_down(&keys))?;
+                // the kernel repeats the key itself then
+                self.next_repeat = (!self.config.kernel_repeat)
+                    .then_some(self.config.repeat_delay)
+                    .flatten()
+                    .map(|delay| Instant::now() + delay);
+            }
+        }
+        Ok(())
+    }
+
+    /// A finger touched the touchpad while no other finger was on it.
+    fn touch(&mut self) -> Result<(), Error> {
+        self.state.last_touch.pos_x = self.state.pos_x;
+        self.state.last_touch.pos_y = self.state.pos_y;
+        self.state.last_touch.time = Instant::now();
+        self.state.phase = Phase::Touching;
+        let item = self.item_at(self.state.pos_x, self.state.pos_y);
+        let (key, hold_key) = (
+            item.map(RowItem::item).copied(),
+            item.and_then(RowItem::hold_item).copied(),
+        );
+        self.state.last_touch.key = key;
+        self.state.last_touch.hold_key = hold_key;
+        if self.state.is_active
+            && // if the user touches a place which is not in the layout it is considered as normal mouse movement; we don't need to grab.
+            self.state.last_touch.key.is_some()
+        {
+            // NOTE: MUST ACTIVATE THE GRAB HERE RATHER THAN SIMPLY GRABBING WHEN ENABLED
+            // AND THEN UNGRABBING/GRABBING WHEN NECESSARY.
+            // IF WE GRAB WHEN ENABLED, DRAGGING WON'T WORK FOR SOME REASON.
+            if self.config.hybrid_grab_delay.is_some() {
+                // it may be the start of moving the pointer
+                self.is_grab_deferred = true;
+            } else {
+                self.grab(GrabMode::Grab)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether any of the contacts on the touchpad is large enough to be a palm.
+    fn is_palm_down(&self) -> bool {
+        let (Some(palm_size), Some(range)) = (self.config.palm_size, self.touch_major_range) else {
+            return false;
+        };
+        self.state.contacts.iter().any(|contact| {
+            contact.tracking_id.is_some() && range.normalize(contact.touch_major) >= palm_size
+        })
+    }
+
+    /// The current touch isn't meant for the numpad, e.g. the user is doing a gesture with multiple fingers
+    /// or resting their palm on the touchpad; let it through until all the fingers are lifted.
+    pub(super) fn ignore_touch(&mut self) -> Result<(), Error> {
+        self.stop_holding_key()?;
+        // the touch is no longer about whatever key it started on, so lifting shouldn't adjust the brightness either
+        self.state.phase = Phase::Dragging;
+        self.state.last_touch.key = None;
+        self.grab(GrabMode::Ungrab)
+    }
+
+    /// The finger moved far enough from where it touched to be a drag rather than a tap or a hold.
+    fn start_drag(&mut self) -> Result<(), Error> {
+        self.stop_holding_key()?;
+        let was_grab_deferred = std::mem::take(&mut self.is_grab_deferred);
+        // the corner may be on the activation key, but the swipe takes precedence since it's how the
+        // stock firmware toggles the numpad
+        if self.is_in_activation_corner(self.state.last_touch.pos_x, self.state.last_touch.pos_y) {
+            self.state.phase = Phase::CornerSwipe;
+            return Ok(());
+        }
+        // if the touched key is the activation key, it means the user is trying to change the brightness,
+        // so we don't need to release the grab on the touchpad
+        if self
+            .state
+            .last_touch
+            .key
+            .is_some_and(|action| self.is_activation(action))
+        {
+            self.state.phase = Phase::BrightnessDrag;
+            self.brightness_drag_levels = 0;
+            if was_grab_deferred {
+                self.grab(GrabMode::Grab)?;
+            }
+            Ok(())
+        } else if self.state.last_touch.key == Some(Action::DeadZone) {
+            // the pointer isn't supposed to move from here either, so keep the grab
+            self.state.phase = Phase::Dragging;
+            if was_grab_deferred {
+                self.grab(GrabMode::Grab)?;
+            }
+            Ok(())
+        } else {
+            // the user wants to move the cursor; ungrab
+            self.state.phase = Phase::Dragging;
+            self.grab(GrabMode::Ungrab)
+        }
+    }
+
+    /// All fingers were lifted; right click if they were two which tapped the numpad, i.e. were lifted
+    /// within the hold duration without moving as far as a drag.
+    fn end_two_finger_tap(&mut self) -> Result<(), Error> {
+        let Some(start) = self.state.two_finger_tap.take() else {
+            return Ok(());
+        };
+        let moved = (self.state.pos_x as f64 - self.state.last_touch.pos_x as f64)
+            .hypot(self.state.pos_y as f64 - self.state.last_touch.pos_y as f64);
+        if start.elapsed() <= self.config.hold_duration && moved < self.config.drag_distance {
+            debug!("two finger tap, right clicking");
+            self.with_keys(|output| output.button_click(EV_KEY::BTN_RIGHT))?;
+        }
+        Ok(())
+    }
+
+    /// Handle an event from the touchpad. This is public so that the logic can be driven by scripted
+    /// events, e.g. with fake parts; otherwise [NumberPad::enter_input_loop] takes care of it.
+    pub fn handle_touchpad_event(&mut self, event: InputEvent) -> Result<(), Error> {
+        trace!(code = %event.event_code, value = event.value, "touchpad event");
+        if self.is_paused {
+            self.follow_paused_touch(&event);
+            return Ok(());
+        }
+        self.wake_up()?;
+        self.state.track_contacts(&event);
+        match event.event_code {
+            EventCode::EV_SYN(EV_SYN::SYN_REPORT) => {
+                // the frame is complete, so we know how many fingers are down and where they are.
+                // only follow the first finger, otherwise a second finger would make it look like the first one jumped
+                if let Some(contact) = self.state.touch_slot.map(|slot| self.state.contacts[slot]) {
+                    self.state.pos_x = contact.pos_x;
+                    self.state.pos_y = contact.pos_y;
+                }
+                let contact_count = self.state.count_contacts();
+                let previous_count =
+                    std::mem::replace(&mut self.state.contact_count, contact_count);
+                match (previous_count, contact_count) {
+                    (0, 1) => self.touch()?,
+                    (1.., 0) => {
+                        self.state.touch_slot = None;
+                        self.lift()?;
+                        self.end_two_finger_tap()?;
+                    }
+                    (1, 2) if let Some(index) = self.touched_chord() => self.start_chord(index)?,
+                    (2, 1) if matches!(self.state.phase, Phase::Chord(_)) => {
+                        self.stop_holding_key()?;
+                    }
+                    (0..=1, 2..) => {
+                        debug!("multiple fingers on the touchpad, letting the gesture through");
+                        if self.config.two_finger_right_click
+                            && self.state.is_active
+                            && previous_count == 1
+                            && contact_count == 2
+                            && self.state.last_touch.key.is_some()
+                        {
+                            self.state.two_finger_tap = Some(Instant::now());
+                        }
+                        self.ignore_touch()?;
+                    }
+                    // a third finger makes it some other gesture
+                    (_, 3..) => self.state.two_finger_tap = None,
+                    _ => (),
+                }
+                // once the touch is ignored there's no key left to press, so this is only checked until then
+                if self.state.last_touch.key.is_some() && self.is_palm_down() {
+                    debug!("a palm is on the touchpad, ignoring the touch");
+                    self.ignore_touch()?;
+                }
+            }
+
+            EventCode::EV_MSC(EV_MSC::MSC_TIMESTAMP) => {
+                // the user is holding; check if they moved far enough from the first touch
+                fn dist(x1: usize, y1: usize, x2: usize, y2: usize) -> f64 {
+                    ((x1 as f64 - x2 as f64).powi(2) + (y1 as f64 - y2 as f64).powi(2)).sqrt()
+                }
+                if self.state.phase == Phase::BrightnessDrag
+                    && self.config.live_brightness_drag
+                    && self.state.is_active
+                {
+                    return self.update_live_brightness_drag();
+                }
+                if !matches!(self.state.phase, Phase::Touching | Phase::Holding(_)) {
+                    return Ok(());
+                }
+                if self.is_grab_deferred
+                    && let Some(delay) = self.config.hybrid_grab_delay
+                    && self.state.phase == Phase::Touching
+                    && Instant::now() - self.state.last_touch.time
+                        >= delay.min(self.config.hold_duration)
+                    && dist(
+                        self.state.pos_x,
+                        self.state.pos_y,
+                        self.state.last_touch.pos_x,
+                        self.state.last_touch.pos_y,
+                    ) < self.config.drag_distance
+                {
+                    // it stayed put, so it's meant for the key
+                    self.is_grab_deferred = false;
+                    self.grab(GrabMode::Grab)?;
+                }
+                if dist(
+                    self.state.pos_x,
+                    self.state.pos_y,
+                    self.state.last_touch.pos_x,
+                    self.state.last_touch.pos_y,
+                ) >= self.config.drag_distance
+                {
+                    return self.start_drag();
+                }
+                if self.state.phase == Phase::Touching {
+                    // the finger may have slid onto a neighbouring item, which is then the one it holds,
+                    // as it's the one lifting it would tap
+                    let item = self.touched_item();
+                    let (key, hold_key) = (
+                        item.map(RowItem::item).copied(),
+                        item.and_then(RowItem::hold_item).copied(),
+                    );
+                    self.state.last_touch.key = key;
+                    self.state.last_touch.hold_key = hold_key;
+                }
+                if let Some(activation_hold) = self.config.activation_hold
+                    && let Some(action) = self.state.last_touch.key
+                    && self.is_activation(action)
+                {
+                    if self.state.phase == Phase::Touching
+                        && Instant::now() - self.state.last_touch.time > activation_hold
+                    {
+                        // lifting the finger afterwards doesn't do anything else
+                        self.state.phase = Phase::Holding(None);
+                        self.set_active(!self.state.is_active)?;
+                    }
+                } else if self.config.activation_hold_command.is_some()
+                    && let Some(action) = self.state.last_touch.key
+                    && self.is_activation(action)
+                    && self.state.last_touch.hold_key.is_none()
+                {
+                    if self.state.phase == Phase::Touching
+                        && Instant::now() - self.state.last_touch.time > self.config.hold_duration
+                    {
+                        // instead of toggling when the finger is lifted
+                        self.state.phase = Phase::Holding(None);
+                        self.run_activation_hold_command();
+                    }
+                } else if self.state.is_active
+                    && self.state.phase == Phase::Touching
+                    && Instant::now() - self.state.last_touch.time > self.config.hold_duration
+                {
+                    self.long_press()?;
+                }
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,268 @@
+use std::{fs, io::ErrorKind, path::Path};
+
+use tracing::{debug, info};
+
+use crate::key_simulation::DEVICE_NAME;
+
+/// Where the touchpad's input device and the i2c bus of its numpad light are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TouchPadId {
+    /// The number of the i2c bus the touchpad is on, i.e. the N in /dev/i2c-N.
+    pub i2c_id: u32,
+    /// The number of the touchpad's event device, i.e. the N in /dev/input/eventN.
+    pub ev_id: u32,
+}
+
+/// Find the first input device whose name `is_touchpad` accepts, other than our own virtual keyboard.
+/// Looks through sysfs, falling back to parsing /proc/bus/input/devices if that fails.
+pub fn find_touchpad(is_touchpad: impl Fn(&str) -> bool) -> std::io::Result<TouchPadId> {
+    from_sysfs(&is_touchpad).or_else(|error| {
+        debug!(%error, "couldn't find the touchpad in sysfs, falling back to /proc/bus/input/devices");
+        from_proc_devices(&is_touchpad)
+    })
+}
+
+fn not_found(seen_names: &[String]) -> std::io::Error {
+    std::io::Error::new(
+        ErrorKind::NotFound,
+        format!(
+            "none of the input devices looks like the touchpad; found {:?}",
+            seen_names
+        ),
+    )
+}
+
+/// Go through the event devices in /sys/class/input for the ones whose name `is_touchpad` accepts
+/// and which are on an i2c bus, and pick the one with the lowest event number.
+fn from_sysfs(is_touchpad: &impl Fn(&str) -> bool) -> std::io::Result<TouchPadId> {
+    let mut touchpads = Vec::new();
+    let mut seen_names = Vec::new();
+    for entry in fs::read_dir("/sys/class/input")? {
+        let entry = entry?;
+        let Some(ev_id) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.strip_prefix("event"))
+            .and_then(|id| id.parse().ok())
+        else {
+            continue;
+        };
+        let device = entry.path().join("device");
+        let Ok(name) = fs::read_to_string(device.join("name")) else {
+            continue;
+        };
+        let name = name.trim();
+        seen_names.push(name.to_string());
+        // the keys we send would come right back
+        if name == DEVICE_NAME || !is_touchpad(name) {
+            continue;
+        }
+        match fs::canonicalize(&device)
+            .ok()
+            .and_then(|path| i2c_bus_of(&path))
+        {
+            Some(i2c_id) => touchpads.push(TouchPadId { i2c_id, ev_id }),
+            None => debug!(
+                name,
+                ev_id, "ignoring a matching device which isn't on an i2c bus"
+            ),
+        }
+    }
+    touchpads.sort_by_key(|touchpad| touchpad.ev_id);
+    if touchpads.len() > 1 {
+        info!("found {} touchpads, using the first one", touchpads.len());
+    }
+    touchpads
+        .first()
+        .copied()
+        .ok_or_else(|| not_found(&seen_names))
+}
+
+/// Get the number of the i2c bus a device is on from its sysfs path,
+/// e.g. 1 for /sys/devices/.../i2c-1/i2c-ASUF1204:00/...
+fn i2c_bus_of(device_path: &Path) -> Option<u32> {
+    device_path.components().rev().find_map(|component| {
+        component
+            .as_os_str()
+            .to_str()?
+            .strip_prefix("i2c-")?
+            .parse()
+            .ok()
+    })
+}
+
+/// Find the touchpad in /proc/bus/input/devices, for when sysfs isn't there.
+fn from_proc_devices(is_touchpad: &impl Fn(&str) -> bool) -> std::io::Result<TouchPadId> {
+    parse_proc_devices(
+        &std::fs::read_to_string("/proc/bus/input/devices")?,
+        is_touchpad,
+    )
+}
+
+/// Find the first device block whose name `is_touchpad` accepts and which has an i2c bus and an event
+/// handler. Blocks which can't be parsed are skipped, and listed in the error if nothing is found.
+fn parse_proc_devices(
+    devices: &str,
+    is_touchpad: &impl Fn(&str) -> bool,
+) -> std::io::Result<TouchPadId> {
+    /// The number right after the first occurrence of `prefix` in the line.
+    fn number_after(line: &str, prefix: &str) -> Option<u32> {
+        let rest = &line[line.find(prefix)? + prefix.len()..];
+        let end = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        rest[..end].parse().ok()
+    }
+
+    // so that we can tell the user what we found if none of them is the touchpad
+    let mut seen_names = Vec::new();
+    let mut unparsable = Vec::new();
+    for block in devices.split("\n\n") {
+        let Some(name) = block
+            .lines()
+            .find_map(|line| line.strip_prefix("N: Name="))
+            .map(|name| name.trim_matches('"'))
+        else {
+            continue;
+        };
+        seen_names.push(name.to_string());
+        if name == DEVICE_NAME || !is_touchpad(name) {
+            continue;
+        }
+        let i2c_id = block
+            .lines()
+            .filter(|line| line.starts_with("S:"))
+            .find_map(|line| number_after(line, "i2c-"));
+        let ev_id = block
+            .lines()
+            .filter(|line| line.starts_with("H:"))
+            .find_map(|line| number_after(line, "event"));
+        match (i2c_id, ev_id) {
+            (Some(i2c_id), Some(ev_id)) => return Ok(TouchPadId { i2c_id, ev_id }),
+            (None, _) => unparsable.push(format!("{:?} has no i2c bus", name)),
+            (_, None) => unparsable.push(format!("{:?} has no event device", name)),
+        }
+    }
+    if unparsable.is_empty() {
+        Err(not_found(&seen_names))
+    } else {
+        Err(std::io::Error::new(
+            ErrorKind::NotFound,
+            format!(
+                "couldn't make sense of the devices which look like the touchpad: {}",
+                unparsable.join(", ")
+            ),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOUCHPAD: &str = "\
+I: Bus=0018 Vendor=0b05 Product=0220 Version=0100
+N: Name=\"ASUE1201:00 04F3:3125 Touchpad\"
+P: Phys=i2c-ASUE1201:00
+S: Sysfs=/devices/pci0000:00/0000:00:15.1/i2c_designware.1/i2c-2/i2c-ASUE1201:00/0018:04F3:3125.0002/input/input12
+U: Uniq=
+H: Handlers=mouse1 event11
+B: PROP=5";
+
+    fn is_touchpad(name: &str) -> bool {
+        name.contains("Touchpad")
+    }
+
+    fn parse(devices: &str) -> std::io::Result<TouchPadId> {
+        parse_proc_devices(devices, &is_touchpad)
+    }
+
+    #[test]
+    fn finds_the_bus_and_the_event_device() {
+        assert_eq!(
+            parse(TOUCHPAD).unwrap(),
+            TouchPadId {
+                i2c_id: 2,
+                ev_id: 11
+            }
+        );
+    }
+
+    #[test]
+    fn truncated_block_has_no_i2c_bus() {
+        let devices = TOUCHPAD.split_once("\nS:").unwrap().0;
+        let error = parse(devices).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+        assert!(error.to_string().contains("has no i2c bus"), "{}", error);
+    }
+
+    #[test]
+    fn block_without_handlers_has_no_event_device() {
+        let devices = TOUCHPAD.replace("H: Handlers=mouse1 event11\n", "");
+        let error = parse(&devices).unwrap_err();
+        assert!(
+            error.to_string().contains("has no event device"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn bad_handlers_have_no_event_device() {
+        for handlers in [
+            "H: Handlers=mouse1",
+            "H: Handlers=mouse1 eventX",
+            "H: Handlers=",
+        ] {
+            let devices = TOUCHPAD.replace("H: Handlers=mouse1 event11", handlers);
+            let error = parse(&devices).unwrap_err();
+            assert!(
+                error.to_string().contains("has no event device"),
+                "{}: {}",
+                handlers,
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn malformed_blocks_are_skipped() {
+        let devices = format!(
+            "I: Bus=0011 Vendor=0001\n\nN: Name=\"Broken Touchpad\"\n\n{}",
+            TOUCHPAD
+        );
+        assert_eq!(parse(&devices).unwrap().ev_id, 11);
+    }
+
+    #[test]
+    fn our_own_device_is_skipped() {
+        let own_device = format!(
+            "N: Name=\"{}\"\nS: Sysfs=/devices/i2c-9/input/input30\nH: Handlers=sysrq kbd event29",
+            DEVICE_NAME
+        );
+        let devices = format!("{}\n\n{}", own_device, TOUCHPAD);
+        // even if the matcher would accept it
+        assert_eq!(
+            parse_proc_devices(&devices, &|_: &str| true).unwrap().ev_id,
+            11
+        );
+        let error = parse_proc_devices(&own_device, &|_: &str| true).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+        assert!(
+            error.to_string().contains("none of the input devices"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn nothing_matching_lists_the_names() {
+        let error = parse_proc_devices(TOUCHPAD, &|_: &str| false).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+        assert!(
+            error.to_string().contains("ASUE1201:00 04F3:3125 Touchpad"),
+            "{}",
+            error
+        );
+    }
+}
@@ -0,0 +1,110 @@
+use std::{fs::File, os::fd::AsRawFd, time::Duration};
+
+use evdev_rs::{
+    Device, DeviceWrapper,
+    enums::{EV_FF, EventCode, EventType},
+};
+use libc::{ff_effect, ff_rumble_effect, input_event};
+use tracing::debug;
+
+/// How long the vibration for a key press lasts.
+const FEEDBACK_DURATION: Duration = Duration::from_millis(20);
+
+/// A short vibration of the touchpad, which confirms key presses since the touchpad doesn't click.
+pub struct Haptics {
+    /// The touchpad's device file, which the effect was uploaded to.
+    device: File,
+    effect_id: i16,
+}
+
+impl Haptics {
+    /// Upload a vibration of the given strength (0.0 to 1.0) to the touchpad.
+    /// Returns None if the touchpad can't vibrate.
+    pub fn new(touchpad: &Device, strength: f64) -> Option<Self> {
+        if !touchpad.has_event_code(&EventCode::EV_FF(EV_FF::FF_RUMBLE)) {
+            debug!("the touchpad doesn't support force feedback");
+            return None;
+        }
+        // cloned first, so that the effect isn't left behind if this fails
+        let device = touchpad.file().try_clone().ok()?;
+        let magnitude = (strength.clamp(0.0, 1.0) * u16::MAX as f64) as u16;
+        // SAFETY: ff_effect is a plain C struct of integers and a union of them, for which all zeros
+        // is a valid value
+        let mut effect: ff_effect = unsafe { std::mem::zeroed() };
+        effect.type_ = EV_FF::FF_RUMBLE as u16;
+        // let the kernel pick the id
+        effect.id = -1;
+        effect.replay.length = FEEDBACK_DURATION.as_millis() as u16;
+        // the effect's parameters are a union in the kernel
+        // SAFETY: the union is at least as large as ff_rumble_effect and suitably aligned for it, since
+        // the kernel's union has it as a member, and the type above says it's the member in use
+        unsafe {
+            std::ptr::write(
+                effect.u.as_mut_ptr() as *mut ff_rumble_effect,
+                ff_rumble_effect {
+                    strong_magnitude: magnitude,
+                    weak_magnitude: magnitude,
+                },
+            )
+        };
+        // EVIOCSFF
+        let upload = libc::_IOW::<ff_effect>(b'E' as u32, 0x80);
+        // SAFETY: EVIOCSFF reads the ff_effect the pointer points to, which lives until the call
+        // returns, and writes the id it picked back into it
+        if unsafe { libc::ioctl(device.as_raw_fd(), upload, &mut effect) } < 0 {
+            debug!(
+                "couldn't upload the vibration to the touchpad: {}",
+                std::io::Error::last_os_error()
+            );
+            return None;
+        }
+        Some(Self {
+            device,
+            effect_id: effect.id,
+        })
+    }
+
+    /// Vibrate once.
+    pub fn play(&self) -> std::io::Result<()> {
+        // SAFETY: input_event is a plain C struct of integers, for which all zeros is a valid value
+        let mut event: input_event = unsafe { std::mem::zeroed() };
+        event.type_ = EventType::EV_FF as u16;
+        event.code = self.effect_id as u16;
+        event.value = 1;
+        // SAFETY: the pointer is to an input_event of the given size, which lives until the call returns
+        let written = unsafe {
+            libc::write(
+                self.device.as_raw_fd(),
+                &event as *const input_event as *const libc::c_void,
+                size_of::<input_event>(),
+            )
+        };
+        if written < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Haptics {
+    /// Remove the effect from the touchpad, which only has room for a few of them; otherwise every
+    /// reload and reconnect would leave one behind.
+    fn drop(&mut self) {
+        // EVIOCRMFF, which takes the effect's id itself rather than a pointer to it
+        let remove = libc::_IOW::<libc::c_int>(b'E' as u32, 0x81);
+        // SAFETY: EVIOCRMFF doesn't dereference its argument
+        if unsafe {
+            libc::ioctl(
+                self.device.as_raw_fd(),
+                remove,
+                self.effect_id as libc::c_int,
+            )
+        } < 0
+        {
+            debug!(
+                "couldn't remove the vibration from the touchpad: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+}
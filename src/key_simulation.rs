@@ -1,85 +1,372 @@
-use std::io::ErrorKind;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use evdev_rs::{
-    DeviceWrapper, InputEvent, TimeVal, UInputDevice, UninitDevice,
-    enums::{EV_KEY, EV_SYN, EventCode},
+    DeviceWrapper, EnableCodeData, InputEvent, TimeVal, UInputDevice, UninitDevice,
+    enums::{EV_KEY, EV_REP, EV_SYN, EventCode},
 };
+use tracing::warn;
 
-static KEYS: &[EV_KEY] = &[
-    EV_KEY::KEY_NUMLOCK,
-    EV_KEY::KEY_BACKSPACE,
-    EV_KEY::KEY_ENTER,
-    EV_KEY::KEY_SLASH,
-    EV_KEY::KEY_KPASTERISK,
-    EV_KEY::KEY_MINUS,
-    EV_KEY::KEY_KPPLUS,
-    EV_KEY::KEY_DOT,
-    EV_KEY::KEY_0,
-    EV_KEY::KEY_1,
-    EV_KEY::KEY_2,
-    EV_KEY::KEY_3,
-    EV_KEY::KEY_4,
-    EV_KEY::KEY_5,
-    EV_KEY::KEY_6,
-    EV_KEY::KEY_7,
-    EV_KEY::KEY_8,
-    EV_KEY::KEY_9,
-];
+/// Parse a key's name, e.g. "KEY_7", "7", "KPPLUS" or "NUMLOCK"; the "KEY_" prefix is optional.
+/// Any key evdev knows of is accepted, not only the ones the numpad uses.
+pub fn parse_ev_key(name: &str) -> Option<EV_KEY> {
+    name.parse()
+        .or_else(|_| format!("KEY_{}", name).parse())
+        .ok()
+}
+
+/// The name of a key as [parse_ev_key] accepts it, e.g. "KEY_7".
+pub fn ev_key_name(key: EV_KEY) -> String {
+    format!("{:?}", key)
+}
+
+/// Whether the key is a mouse button, i.e. one of BTN_LEFT to BTN_TASK.
+pub fn is_mouse_button(key: EV_KEY) -> bool {
+    (EV_KEY::BTN_LEFT as u32..=EV_KEY::BTN_TASK as u32).contains(&(key as u32))
+}
+
+/// Whether the system's NumLock is on, going by the keyboards' NumLock LEDs.
+/// Returns None if there's no keyboard with a NumLock LED.
+pub fn system_numlock() -> Option<bool> {
+    let mut numlock = None;
+    for entry in std::fs::read_dir("/sys/class/leds").ok()?.flatten() {
+        if !entry.file_name().to_string_lossy().ends_with("::numlock") {
+            continue;
+        }
+        if let Ok(brightness) = std::fs::read_to_string(entry.path().join("brightness")) {
+            let is_on = brightness.trim() != "0";
+            numlock = Some(numlock.unwrap_or(false) || is_on);
+        }
+    }
+    numlock
+}
+
+/// The current time, as the kernel stamps input events with it by default.
+fn now() -> TimeVal {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    TimeVal::new(
+        since_epoch.as_secs() as libc::time_t,
+        since_epoch.subsec_micros() as libc::suseconds_t,
+    )
+}
+
+/// How to type a character which has no key of its own: by pressing the `start` keys together, typing
+/// the character's code point in hexadecimal and pressing the `end` keys together, as with the Ctrl+Shift+U
+/// which GTK and IBus understand, the default. `end` may be empty, e.g. if the start keys are held
+/// during the whole sequence by the input method anyway.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnicodeInput {
+    pub start: Vec<EV_KEY>,
+    pub end: Vec<EV_KEY>,
+}
+
+impl Default for UnicodeInput {
+    fn default() -> Self {
+        Self {
+            start: vec![EV_KEY::KEY_LEFTCTRL, EV_KEY::KEY_LEFTSHIFT, EV_KEY::KEY_U],
+            end: vec![EV_KEY::KEY_SPACE],
+        }
+    }
+}
+
+impl UnicodeInput {
+    /// The keys which type a hexadecimal digit, from KEY_0 to KEY_F.
+    fn digit_key(digit: char) -> EV_KEY {
+        // every hexadecimal digit is the name of a key
+        parse_ev_key(&digit.to_ascii_uppercase().to_string()).unwrap()
+    }
+
+    /// The presses which type the character, in order; the keys of each are pressed together.
+    pub fn sequence(&self, character: char) -> Vec<Vec<EV_KEY>> {
+        let digits = format!("{:x}", character as u32);
+        std::iter::once(self.start.clone())
+            .chain(digits.chars().map(|digit| vec![Self::digit_key(digit)]))
+            .chain(Some(self.end.clone()).filter(|end| !end.is_empty()))
+            .collect()
+    }
+
+    /// Every key which typing characters can press.
+    pub fn keys(&self) -> Vec<EV_KEY> {
+        let digits = "0123456789abcdef".chars().map(Self::digit_key);
+        self.start
+            .iter()
+            .chain(&self.end)
+            .copied()
+            .chain(digits)
+            .collect()
+    }
+}
+
+/// The name of the virtual keyboard, which is never taken for the touchpad.
+pub const DEVICE_NAME: &str = "NumberPad";
+
+/// The ids the virtual keyboard reports, as in the kernel's `struct input_id`, which udev rules and
+/// remapping tools can match it by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputId {
+    pub bustype: u16,
+    pub vendor: u16,
+    pub product: u16,
+    pub version: u16,
+}
+
+impl InputId {
+    /// BUS_VIRTUAL, since there's no hardware behind the device.
+    pub const BUS_VIRTUAL: u16 = 0x06;
+}
+
+impl Default for InputId {
+    /// BUS_VIRTUAL, vendor 0x4e50 ("NP"), product 0x0001 and version 0x0001.
+    fn default() -> Self {
+        Self {
+            bustype: Self::BUS_VIRTUAL,
+            vendor: 0x4e50,
+            product: 0x0001,
+            version: 0x0001,
+        }
+    }
+}
 
 pub struct KeySimulator {
     pub udev: UInputDevice,
+    /// The keys which were enabled, see [KeySimulator::release_all] and [KeySimulator::keys].
+    keys: Vec<EV_KEY>,
 }
 
 impl KeySimulator {
-    const KEY_DOWN: i32 = 1;
-    const KEY_UP: i32 = 0;
-    pub fn new() -> std::io::Result<Self> {
-        let dev = UninitDevice::new().ok_or(std::io::Error::new(
-            ErrorKind::Other,
+    /// The values of key events, see [KeySimulator::send_keys].
+    pub const KEY_DOWN: i32 = 1;
+    pub const KEY_UP: i32 = 0;
+    pub const KEY_REPEAT: i32 = 2;
+    /// Create the virtual keyboard with the default [InputId], which can send the given keys; it
+    /// silently drops any other key, so they should be all of the keys the layout uses. Mouse buttons (see [is_mouse_button]) can be
+    /// given too, but only the ones which are used should be, since the device advertises them.
+    /// A key which couldn't be enabled is left out with a warning, see [KeySimulator::keys].
+    pub fn new(keys: &[EV_KEY]) -> std::io::Result<Self> {
+        Self::create(keys, InputId::default(), None)
+    }
+
+    /// Like [KeySimulator::new], but the device advertises EV_REP, so that the kernel repeats held keys
+    /// after the delay, once per period, and consumers which repeat keys themselves use the same
+    /// timing. Keys shouldn't be repeated with [KeySimulator::keys_repeat] as well then.
+    pub fn with_repeat(
+        keys: &[EV_KEY],
+        delay: Duration,
+        period: Duration,
+    ) -> std::io::Result<Self> {
+        Self::create(keys, InputId::default(), Some((delay, period)))
+    }
+
+    /// Like [KeySimulator::new], with the given ids and, if `repeat` is a delay and a period, with
+    /// EV_REP as [KeySimulator::with_repeat] does.
+    pub fn create(
+        keys: &[EV_KEY],
+        id: InputId,
+        repeat: Option<(Duration, Duration)>,
+    ) -> std::io::Result<Self> {
+        let dev = UninitDevice::new().ok_or(std::io::Error::other(
             "could not create an uninitialized device",
         ))?;
-        dev.set_name("NumberPad");
-        for key in KEYS {
-            dev.enable(EventCode::EV_KEY(*key))
-                .expect(&format!("could not enable {:?}", key));
+        dev.set_name(DEVICE_NAME);
+        dev.set_bustype(id.bustype);
+        dev.set_vendor_id(id.vendor);
+        dev.set_product_id(id.product);
+        dev.set_version(id.version);
+        let mut enabled = Vec::with_capacity(keys.len());
+        for key in keys {
+            let code = EventCode::EV_KEY(*key);
+            if dev.enable(code).is_err() || !dev.has(code) {
+                warn!(?key, "couldn't enable the key, it won't be sent");
+            } else if !enabled.contains(key) {
+                enabled.push(*key);
+            }
         }
 
-        let udev = UInputDevice::create_from_device(&dev)?;
-        Ok(Self { udev })
-    }
+        let repeat = repeat.map(|(delay, period)| {
+            [(EV_REP::REP_DELAY, delay), (EV_REP::REP_PERIOD, period)]
+                .map(|(code, duration)| (code, duration.as_millis().min(i32::MAX as u128) as i32))
+        });
+        if let Some(repeat) = &repeat {
+            for (code, millis) in repeat {
+                dev.enable_event_code(
+                    &EventCode::EV_REP(*code),
+                    Some(EnableCodeData::RepInfo(*millis)),
+                )?;
+            }
+        }
 
-    fn syn(&self) {
-        self.udev
-            .write_event(&InputEvent::new(
-                &TimeVal::new(0, 0),
+        let udev = UInputDevice::create_from_device(&dev)?;
+        // uinput only takes the EV_REP bit from the device, the kernel's own defaults are used until
+        // the values are sent as events
+        if let Some(repeat) = repeat {
+            let time = now();
+            for (code, millis) in repeat {
+                udev.write_event(&InputEvent::new(&time, &EventCode::EV_REP(code), millis))?;
+            }
+            udev.write_event(&InputEvent::new(
+                &time,
                 &EventCode::EV_SYN(EV_SYN::SYN_REPORT),
                 0,
-            ))
-            .unwrap();
+            ))?;
+        }
+        Ok(Self {
+            udev,
+            keys: enabled,
+        })
     }
 
-    fn send_key_event(&self, keys: &[EV_KEY], event: i32) {
-        for key in keys {
+    fn syn(&self, time: &TimeVal) -> std::io::Result<()> {
+        self.udev.write_event(&InputEvent::new(
+            time,
+            &EventCode::EV_SYN(EV_SYN::SYN_REPORT),
+            0,
+        ))
+    }
+
+    /// Send the (key, value) events in order, followed by a single SYN_REPORT, so that consumers see
+    /// them all at once. The values are [KeySimulator::KEY_DOWN], [KeySimulator::KEY_UP] or
+    /// [KeySimulator::KEY_REPEAT].
+    pub fn send_keys(&self, events: &[(EV_KEY, i32)]) -> std::io::Result<()> {
+        // the whole report happens at once
+        let time = now();
+        for (key, value) in events {
             self.udev
-                .write_event(&InputEvent::new(
-                    &TimeVal::new(0, 0),
-                    &EventCode::EV_KEY(*key),
-                    event,
-                ))
-                .unwrap();
+                .write_event(&InputEvent::new(&time, &EventCode::EV_KEY(*key), *value))?;
         }
-        self.syn();
+        self.syn(&time)
+    }
+
+    fn send_key_event(&self, keys: &[EV_KEY], value: i32) -> std::io::Result<()> {
+        let events: Vec<(EV_KEY, i32)> = keys.iter().map(|key| (*key, value)).collect();
+        self.send_keys(&events)
     }
-    pub fn keys_down(&self, keys: &[EV_KEY]) {
-        self.send_key_event(keys, Self::KEY_DOWN);
+    pub fn keys_down(&self, keys: &[EV_KEY]) -> std::io::Result<()> {
+        self.send_key_event(keys, Self::KEY_DOWN)
+    }
+
+    pub fn keys_up(&self, keys: &[EV_KEY]) -> std::io::Result<()> {
+        self.send_key_event(keys, Self::KEY_UP)
+    }
+
+    /// Send an autorepeat event for keys which are being held down.
+    pub fn keys_repeat(&self, keys: &[EV_KEY]) -> std::io::Result<()> {
+        self.send_key_event(keys, Self::KEY_REPEAT)
+    }
+
+    /// The device file the virtual keyboard got, e.g. "/dev/input/event20", or None if it isn't known.
+    pub fn devnode(&self) -> Option<&str> {
+        self.udev.devnode()
+    }
+
+    /// The virtual keyboard's directory in sysfs, e.g. "/sys/devices/virtual/input/input42", or None if
+    /// it isn't known.
+    pub fn syspath(&self) -> Option<&str> {
+        self.udev.syspath()
+    }
+
+    /// The keys the device can send, which are the ones it was created with but for any which
+    /// couldn't be enabled.
+    pub fn keys(&self) -> &[EV_KEY] {
+        &self.keys
     }
 
-    pub fn keys_up(&self, keys: &[EV_KEY]) {
-        self.send_key_event(keys, Self::KEY_UP);
+    /// Release every key this can send, whether it's down or not, e.g. so that none is left stuck down
+    /// when we're going away. Releasing a key which isn't down does nothing.
+    pub fn release_all(&self) -> std::io::Result<()> {
+        self.keys_up(&self.keys)
     }
 
-    pub fn keys_press(&self, keys: &[EV_KEY]) {
-        self.keys_down(keys);
-        self.keys_up(keys);
+    /// Click a mouse button such as BTN_RIGHT, which has to be one of the keys this was created with.
+    /// Returns an error of kind [std::io::ErrorKind::InvalidInput] for anything but a mouse button.
+    pub fn button_click(&self, button: EV_KEY) -> std::io::Result<()> {
+        if !is_mouse_button(button) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{:?} isn't a mouse button", button),
+            ));
+        }
+        self.keys_press(&[button])
+    }
+
+    /// Press the keys together and release them in reverse order, e.g. a modifier and then a key.
+    /// Pressing and releasing are separate reports, since a key which goes down and up in the same
+    /// report may be missed.
+    pub fn keys_press(&self, keys: &[EV_KEY]) -> std::io::Result<()> {
+        self.keys_down(keys)?;
+        let released: Vec<EV_KEY> = keys.iter().rev().copied().collect();
+        self.keys_up(&released)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::{Action, default_numpad_layout};
+
+    #[test]
+    fn the_default_layouts_keys_round_trip_through_their_names() {
+        let keys: Vec<EV_KEY> = default_numpad_layout()
+            .items()
+            .filter_map(|action| match action {
+                Action::Key(key) => Some(*key),
+                _ => None,
+            })
+            .collect();
+        assert!(keys.contains(&EV_KEY::KEY_7) && keys.contains(&EV_KEY::KEY_0));
+        for key in keys {
+            assert_eq!(parse_ev_key(&ev_key_name(key)), Some(key), "{:?}", key);
+        }
+    }
+
+    #[test]
+    fn parse_ev_key_takes_names_with_or_without_the_prefix() {
+        assert_eq!(parse_ev_key("7"), Some(EV_KEY::KEY_7));
+        assert_eq!(parse_ev_key("KEY_7"), Some(EV_KEY::KEY_7));
+        assert_eq!(parse_ev_key("KPPLUS"), Some(EV_KEY::KEY_KPPLUS));
+        assert_eq!(parse_ev_key("NUMLOCK"), Some(EV_KEY::KEY_NUMLOCK));
+        assert_eq!(parse_ev_key("BTN_RIGHT"), Some(EV_KEY::BTN_RIGHT));
+    }
+
+    #[test]
+    fn unicode_input_types_the_code_point_in_hexadecimal() {
+        let input = UnicodeInput::default();
+        assert_eq!(
+            input.sequence('€'),
+            [
+                input.start.clone(),
+                vec![EV_KEY::KEY_2],
+                vec![EV_KEY::KEY_0],
+                vec![EV_KEY::KEY_A],
+                vec![EV_KEY::KEY_C],
+                input.end.clone(),
+            ]
+        );
+        for key in input.sequence('€').concat() {
+            assert!(input.keys().contains(&key), "{:?}", key);
+        }
+    }
+
+    #[test]
+    fn unicode_input_leaves_an_empty_end_out() {
+        let input = UnicodeInput {
+            start: vec![EV_KEY::KEY_LEFTCTRL, EV_KEY::KEY_LEFTSHIFT],
+            end: Vec::new(),
+        };
+        assert_eq!(
+            input.sequence('µ'),
+            [
+                vec![EV_KEY::KEY_LEFTCTRL, EV_KEY::KEY_LEFTSHIFT],
+                vec![EV_KEY::KEY_B],
+                vec![EV_KEY::KEY_5],
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_ev_key_rejects_unknown_names() {
+        for name in ["", "KEY_", "KEY_NOPE", "kp7", "KEY_KP7 ", "BRIGHTNESS_UP"] {
+            assert_eq!(parse_ev_key(name), None, "{:?}", name);
+        }
     }
 }
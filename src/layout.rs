@@ -1,105 +1,1271 @@
+use std::{
+    fmt::{Debug, Display},
+    str::FromStr,
+};
+
 use evdev_rs::enums::EV_KEY;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::key_simulation::{ev_key_name, parse_ev_key};
+
+/// Rows are named by their (min_y, max_y), since they are sorted, so their index wouldn't be the one
+/// in the layout file, and items by their key, e.g. KEY_7.
+#[derive(Debug, Error)]
+pub enum LayoutError {
+    #[error("a row has inverted bounds: min_y {} is greater than max_y {}", .min_y, .max_y)]
+    InvertedRowBounds { min_y: usize, max_y: usize },
+    #[error("{} in the row from y {} to {} has inverted bounds: left_x {} is greater than right_x {}", .item, .row.0, .row.1, .left_x, .right_x)]
+    InvertedItemBounds {
+        row: (usize, usize),
+        item: String,
+        left_x: usize,
+        right_x: usize,
+    },
+    #[error("{} and {} overlap in the row from y {} to {}", .first, .second, .row.0, .row.1)]
+    OverlappingItems {
+        row: (usize, usize),
+        first: String,
+        second: String,
+    },
+    #[error("the rows from y {} to {} and from y {} to {} overlap", .first.0, .first.1, .second.0, .second.1)]
+    OverlappingRows {
+        first: (usize, usize),
+        second: (usize, usize),
+    },
+    #[error("the row from y {} to {} goes outside of the layout's size {:?}", .row.0, .row.1, .size)]
+    OutOfBounds {
+        row: (usize, usize),
+        size: (usize, usize),
+    },
+    #[error("unknown key or action {:?}", .0)]
+    UnknownAction(String),
+}
+
+/// What touching a layout item does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(try_from = "String", into = "String")
+)]
+pub enum Action {
+    Key(EV_KEY),
+    /// Activate the numpad if it's inactive and vice versa; dragging from it changes the brightness.
+    /// The item which [Config::activation_key](crate::config::Config::activation_key) names does the same.
+    ToggleActive,
+    /// Raise the light's brightness by a level.
+    BrightnessUp,
+    /// Lower the light's brightness by a level.
+    BrightnessDown,
+    /// A sticky modifier such as KEY_LEFTCTRL: tapping it presses it along with the next key,
+    /// tapping it twice keeps it pressed along with every key until it's tapped again.
+    Modifier(EV_KEY),
+    /// Switch to the next of the numpad's layouts.
+    NextLayout,
+    /// Switch to the layout with this index, where the main layout is 0 and the others follow in the
+    /// order of their names.
+    SwitchLayout(usize),
+    /// Nothing, but unlike the margins between the items and the area outside of them, which stay usable
+    /// for moving the pointer while the numpad is active, touching or dragging from it doesn't move the
+    /// pointer either.
+    DeadZone,
+    /// Type a character which has no key of its own, as
+    /// [Config::unicode_input](crate::config::Config::unicode_input) says.
+    Unicode(char),
+}
+
+impl From<EV_KEY> for Action {
+    fn from(key: EV_KEY) -> Self {
+        Self::Key(key)
+    }
+}
+
+impl FromStr for Action {
+    type Err = LayoutError;
+
+    /// Parse an action's name, i.e. "TOGGLE_ACTIVE", "BRIGHTNESS_UP", "BRIGHTNESS_DOWN", "NEXT_LAYOUT",
+    /// "DEAD_ZONE", "SWITCH_LAYOUT_" followed by the layout's index, a key name such as "KEY_7" or "NUMLOCK", a key
+    /// name prefixed with "MOD_" for a modifier, e.g. "MOD_LEFTCTRL", or "U+" followed by a character's
+    /// code point in hexadecimal, e.g. "U+00B0" for "°"; the "KEY_" prefix is optional.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "TOGGLE_ACTIVE" => Ok(Self::ToggleActive),
+            "BRIGHTNESS_UP" => Ok(Self::BrightnessUp),
+            "BRIGHTNESS_DOWN" => Ok(Self::BrightnessDown),
+            "NEXT_LAYOUT" => Ok(Self::NextLayout),
+            "DEAD_ZONE" => Ok(Self::DeadZone),
+            _ => if let Some(index) = name.strip_prefix("SWITCH_LAYOUT_") {
+                index.parse().ok().map(Self::SwitchLayout)
+            } else if let Some(code_point) = name.strip_prefix("U+") {
+                u32::from_str_radix(code_point, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .map(Self::Unicode)
+            } else if let Some(key_name) = name.strip_prefix("MOD_") {
+                parse_ev_key(key_name).map(Self::Modifier)
+            } else {
+                parse_ev_key(name).map(Self::Key)
+            }
+            .ok_or_else(|| LayoutError::UnknownAction(name.to_string())),
+        }
+    }
+}
 
+impl Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Key(key) => write!(f, "{}", ev_key_name(*key)),
+            Self::ToggleActive => write!(f, "TOGGLE_ACTIVE"),
+            Self::BrightnessUp => write!(f, "BRIGHTNESS_UP"),
+            Self::BrightnessDown => write!(f, "BRIGHTNESS_DOWN"),
+            Self::Modifier(key) => write!(f, "MOD_{}", ev_key_name(*key)),
+            Self::NextLayout => write!(f, "NEXT_LAYOUT"),
+            Self::SwitchLayout(index) => write!(f, "SWITCH_LAYOUT_{}", index),
+            Self::DeadZone => write!(f, "DEAD_ZONE"),
+            Self::Unicode(character) => write!(f, "U+{:04X}", *character as u32),
+        }
+    }
+}
+
+impl TryFrom<String> for Action {
+    type Error = LayoutError;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        name.parse()
+    }
+}
+
+impl From<Action> for String {
+    fn from(action: Action) -> Self {
+        action.to_string()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(bound(deserialize = "T: Deserialize<'de>"))
+)]
 pub struct RowItem<T> {
     left_x: usize,
     right_x: usize,
+    // named `key` to match the layout file format; actions are (de)serialized by their name, e.g. "KEY_7"
+    #[cfg_attr(feature = "serde", serde(rename = "key"))]
     item: T,
+    /// What touching the item for longer than the hold duration does instead, if anything else.
+    #[cfg_attr(
+        feature = "serde",
+        serde(rename = "hold_key", default, skip_serializing_if = "Option::is_none")
+    )]
+    hold_item: Option<T>,
 }
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(bound(deserialize = "T: Deserialize<'de>"))
+)]
 pub struct Row<T> {
     items: Vec<RowItem<T>>,
     max_y: usize,
     min_y: usize,
 }
+/// Deserializing a layout sorts and validates it, like [crate::config::load_layout] does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(
+        try_from = "LayoutFile<T>",
+        bound(deserialize = "T: Deserialize<'de> + Display")
+    )
+)]
 pub struct Layout<T> {
+    /// Sorted by min_y, with each row's items sorted by left_x, so that get_item can binary search.
+    rows: Vec<Row<T>>,
+    /// If set, the coordinates are relative to a touchpad of this (width, height) instead of being
+    /// the device's raw coordinates, which makes the layout independent of the touchpad's resolution.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    size: Option<(usize, usize)>,
+}
+
+/// A layout as it's deserialized, before it's sorted and validated.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(bound(deserialize = "T: Deserialize<'de>"))]
+struct LayoutFile<T> {
     rows: Vec<Row<T>>,
+    #[serde(default)]
+    size: Option<(usize, usize)>,
+}
+
+#[cfg(feature = "serde")]
+impl<T: Display> TryFrom<LayoutFile<T>> for Layout<T> {
+    type Error = LayoutError;
+
+    fn try_from(file: LayoutFile<T>) -> Result<Self, Self::Error> {
+        let mut layout = Self::new(file.rows);
+        layout.size = file.size;
+        layout.validate()?;
+        Ok(layout)
+    }
+}
+
+/// A rectangle in a layout's coordinates. Like the bounds of rows and items, the edges are inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub left: usize,
+    pub top: usize,
+    pub right: usize,
+    pub bottom: usize,
+}
+
+/// How [Grid::layout] divides an area into cells.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grid {
+    area: Rect,
+    gap_x: u32,
+    gap_y: u32,
+    column_widths: Option<Vec<usize>>,
+}
+
+impl Grid {
+    /// Evenly sized cells filling the area, without gaps between them.
+    pub fn new(area: Rect) -> Self {
+        Self {
+            area,
+            gap_x: 0,
+            gap_y: 0,
+            column_widths: None,
+        }
+    }
+
+    /// Leave `x` coordinates between neighbouring columns and `y` between neighbouring rows, which
+    /// belong to no cell. The bounds are inclusive, so cells never share a boundary even without a gap.
+    pub fn gap(mut self, x: u32, y: u32) -> Self {
+        self.gap_x = x;
+        self.gap_y = y;
+        self
+    }
+
+    /// Make the columns as wide as each other as these numbers are, counting the gap after each
+    /// column. There are as many columns as numbers then, and items past the last one are left out.
+    pub fn column_widths(mut self, widths: &[usize]) -> Self {
+        self.column_widths = Some(widths.to_vec());
+        self
+    }
+
+    /// Every row of `items` is a row of the grid; None leaves a cell empty, as do the missing items at
+    /// the end of shorter rows. Unless [Grid::column_widths] says otherwise, there are as many columns
+    /// as the longest row has items. If the area is too small for the gaps, the cells come out inverted,
+    /// which [Layout::validate] reports.
+    pub fn layout<T: Clone>(&self, items: &[&[Option<T>]]) -> Layout<T> {
+        let Rect {
+            left,
+            top,
+            right,
+            bottom,
+        } = self.area;
+        let column_widths = self
+            .column_widths
+            .clone()
+            .unwrap_or_else(|| vec![1; items.iter().map(|row| row.len()).max().unwrap_or(0)]);
+        let columns = cells(left, right, self.gap_x as usize, &column_widths);
+        let rows = cells(top, bottom, self.gap_y as usize, &vec![1; items.len()]);
+        let rows = items
+            .iter()
+            .zip(rows)
+            .map(|(row, (min_y, max_y))| {
+                let row_items = row
+                    .iter()
+                    .zip(&columns)
+                    .filter_map(|(item, (left_x, right_x))| {
+                        Some(RowItem::new(*left_x, *right_x, item.clone()?))
+                    })
+                    .collect();
+                Row::new(min_y, max_y, row_items)
+            })
+            .collect();
+        Layout::new(rows)
+    }
+}
+
+/// Divide the inclusive range into cells as wide as each other as `widths` are, with `gap` between
+/// them, as if every cell had half of a gap on either side, with the outer halves cut off.
+fn cells(start: usize, end: usize, gap: usize, widths: &[usize]) -> Vec<(usize, usize)> {
+    let span = (end + 1).saturating_sub(start) + gap;
+    let total = widths.iter().sum::<usize>().max(1);
+    let mut before = 0;
+    widths
+        .iter()
+        .map(|width| {
+            let cell_start = start + before * span / total;
+            before += width;
+            let cell_end = (start + before * span / total).saturating_sub(gap + 1);
+            (cell_start, cell_end)
+        })
+        .collect()
+}
+
+impl<T> RowItem<T> {
+    pub fn new(left_x: usize, right_x: usize, item: T) -> Self {
+        Self {
+            left_x,
+            right_x,
+            item,
+            hold_item: None,
+        }
+    }
+
+    /// Give the item something else to do when it's held, see [RowItem::hold_item].
+    pub fn with_hold_item(mut self, hold_item: T) -> Self {
+        self.hold_item = Some(hold_item);
+        self
+    }
+
+    pub fn item(&self) -> &T {
+        &self.item
+    }
+
+    pub fn left_x(&self) -> usize {
+        self.left_x
+    }
+
+    pub fn right_x(&self) -> usize {
+        self.right_x
+    }
+
+    /// What holding the item does instead of holding down [RowItem::item], or None if it does the same.
+    pub fn hold_item(&self) -> Option<&T> {
+        self.hold_item.as_ref()
+    }
 }
 
-impl<T: Clone> Layout<T> {
-    pub fn get_item(&self, x: usize, y: usize) -> Option<T> {
-        for row in self.rows.iter() {
-            if row.min_y <= y && y <= row.max_y {
-                for item in row.items.iter() {
-                    if item.left_x <= x && x <= item.right_x {
-                        return Some(item.item.clone());
+impl<T> Row<T> {
+    /// The items can be in any order; they are sorted from left to right.
+    pub fn new(min_y: usize, max_y: usize, mut items: Vec<RowItem<T>>) -> Self {
+        items.sort_by_key(|item| item.left_x);
+        Self {
+            items,
+            max_y,
+            min_y,
+        }
+    }
+
+    pub fn min_y(&self) -> usize {
+        self.min_y
+    }
+
+    pub fn max_y(&self) -> usize {
+        self.max_y
+    }
+
+    /// The items from left to right.
+    pub fn items(&self) -> &[RowItem<T>] {
+        &self.items
+    }
+}
+
+/// Sort the rows from top to bottom and their items from left to right.
+fn sort_rows<T>(rows: &mut [Row<T>]) {
+    rows.sort_by_key(|row| row.min_y);
+    for row in rows {
+        row.items.sort_by_key(|item| item.left_x);
+    }
+}
+
+impl<T> Layout<T> {
+    /// The rows can be in any order; they are sorted from top to bottom.
+    pub fn new(mut rows: Vec<Row<T>>) -> Self {
+        sort_rows(&mut rows);
+        Self { rows, size: None }
+    }
+
+    /// Lay the items out as a grid of evenly sized cells filling the area, with `gap` between
+    /// neighbouring cells, e.g. for a touchpad whose numpad's corners are known from calibrating. Every
+    /// row of `items` is a row of the grid, and there are as many columns as the longest one has items;
+    /// None leaves a cell empty, as do the missing items at the end of shorter rows. See [Grid] for
+    /// columns of different widths or different gaps between rows and columns.
+    pub fn grid(items: &[&[Option<T>]], area: Rect, gap: u32) -> Self
+    where
+        T: Clone,
+    {
+        Grid::new(area).gap(gap, gap).layout(items)
+    }
+
+    /// Define the coordinates as relative to a touchpad of the given size, see [Layout::size].
+    pub fn with_size(mut self, width: usize, height: usize) -> Self {
+        self.size = Some((width, height));
+        self
+    }
+
+    /// The (width, height) the coordinates are relative to, or None if they're raw device coordinates.
+    pub fn size(&self) -> Option<(usize, usize)> {
+        self.size
+    }
+
+    /// The rows from top to bottom.
+    pub fn rows(&self) -> &[Row<T>] {
+        &self.rows
+    }
+
+    /// The smallest rectangle containing all the items, or None if there are none.
+    pub fn bounds(&self) -> Option<Rect> {
+        let rows = self.rows.iter().filter(|row| !row.items.is_empty());
+        rows.fold(None, |bounds, row| {
+            let left = row.items.iter().map(|item| item.left_x).min()?;
+            let right = row.items.iter().map(|item| item.right_x).max()?;
+            let row_bounds = Rect {
+                left,
+                top: row.min_y,
+                right,
+                bottom: row.max_y,
+            };
+            Some(match bounds {
+                None => row_bounds,
+                Some(bounds) => Rect {
+                    left: bounds.left.min(row_bounds.left),
+                    top: bounds.top.min(row_bounds.top),
+                    right: bounds.right.max(row_bounds.right),
+                    bottom: bounds.bottom.max(row_bounds.bottom),
+                },
+            })
+        })
+    }
+
+    /// Move and stretch the layout so that its [Layout::bounds] become the given ones, e.g. to fit it to
+    /// where the numpad is printed on a touchpad. The bounds are in raw device coordinates, so the
+    /// result is too. Rows without items don't count towards the bounds, but they're moved and
+    /// stretched along with the rest, stopping at 0 if that would take them past it.
+    pub fn fit_to(mut self, area: Rect) -> Self {
+        let Some(from) = self.bounds() else {
+            return self;
+        };
+        let scale = |value: usize, from: (usize, usize), to: (usize, usize)| {
+            // an empty row can be outside of the bounds, on either side
+            let progress = (value as f64 - from.0 as f64) / (from.1 - from.0).max(1) as f64;
+            (to.0 as f64 + progress * (to.1 as f64 - to.0 as f64))
+                .round()
+                .max(0.0) as usize
+        };
+        let (from_x, to_x) = ((from.left, from.right), (area.left, area.right));
+        let (from_y, to_y) = ((from.top, from.bottom), (area.top, area.bottom));
+        for row in &mut self.rows {
+            row.min_y = scale(row.min_y, from_y, to_y);
+            row.max_y = scale(row.max_y, from_y, to_y);
+            for item in &mut row.items {
+                item.left_x = scale(item.left_x, from_x, to_x);
+                item.right_x = scale(item.right_x, from_x, to_x);
+            }
+        }
+        self.size = None;
+        self
+    }
+
+    /// Flip the layout horizontally within the given width, e.g. for left handed use, so that an item
+    /// from `left_x` to `right_x` goes from `width - right_x` to `width - left_x`. The items stay the
+    /// same; only where they are changes. Mirroring twice within the same width gives back the original
+    /// layout, except for coordinates beyond the width: they end up at 0, so after mirroring again they're
+    /// at the width.
+    pub fn mirror_x(mut self, width: usize) -> Self {
+        for row in &mut self.rows {
+            for item in &mut row.items {
+                (item.left_x, item.right_x) = (
+                    width.saturating_sub(item.right_x),
+                    width.saturating_sub(item.left_x),
+                );
+            }
+        }
+        sort_rows(&mut self.rows);
+        self
+    }
+
+    /// Every item, from the top left to the bottom right, each followed by its hold item if it has one.
+    pub fn items(&self) -> impl Iterator<Item = &T> {
+        self.rows.iter().flat_map(|row| {
+            row.items
+                .iter()
+                .flat_map(|item| std::iter::once(&item.item).chain(&item.hold_item))
+        })
+    }
+
+    /// Convert every item with the given function, keeping the geometry.
+    pub fn map<U>(self, mut f: impl FnMut(T) -> U) -> Layout<U> {
+        Layout {
+            rows: self
+                .rows
+                .into_iter()
+                .map(|row| Row {
+                    items: row
+                        .items
+                        .into_iter()
+                        .map(|item| RowItem {
+                            left_x: item.left_x,
+                            right_x: item.right_x,
+                            item: f(item.item),
+                            hold_item: item.hold_item.map(&mut f),
+                        })
+                        .collect(),
+                    max_y: row.max_y,
+                    min_y: row.min_y,
+                })
+                .collect(),
+            size: self.size,
+        }
+    }
+}
+
+impl From<Layout<EV_KEY>> for Layout<Action> {
+    fn from(layout: Layout<EV_KEY>) -> Self {
+        layout.map(Action::Key)
+    }
+}
+
+// both ranges are inclusive
+fn ranges_overlap(first: (usize, usize), second: (usize, usize)) -> bool {
+    first.0 <= second.1 && second.0 <= first.1
+}
+
+impl<T: Display> Layout<T> {
+    /// Check that no bounds are inverted and that no two rows (or two items in the same row) overlap,
+    /// since get_item would otherwise silently pick one of them.
+    pub fn validate(&self) -> Result<(), LayoutError> {
+        for (row_index, row) in self.rows.iter().enumerate() {
+            let bounds = (row.min_y, row.max_y);
+            if row.min_y > row.max_y {
+                return Err(LayoutError::InvertedRowBounds {
+                    min_y: row.min_y,
+                    max_y: row.max_y,
+                });
+            }
+            for (item_index, item) in row.items.iter().enumerate() {
+                if item.left_x > item.right_x {
+                    return Err(LayoutError::InvertedItemBounds {
+                        row: bounds,
+                        item: item.item.to_string(),
+                        left_x: item.left_x,
+                        right_x: item.right_x,
+                    });
+                }
+                for other in row.items.iter().skip(item_index + 1) {
+                    if ranges_overlap((item.left_x, item.right_x), (other.left_x, other.right_x)) {
+                        return Err(LayoutError::OverlappingItems {
+                            row: bounds,
+                            first: item.item.to_string(),
+                            second: other.item.to_string(),
+                        });
                     }
                 }
             }
+            if let Some((width, height)) = self.size
+                && (row.max_y > height || row.items.iter().any(|item| item.right_x > width))
+            {
+                return Err(LayoutError::OutOfBounds {
+                    row: bounds,
+                    size: (width, height),
+                });
+            }
+            for other in self.rows.iter().skip(row_index + 1) {
+                if ranges_overlap(bounds, (other.min_y, other.max_y)) {
+                    return Err(LayoutError::OverlappingRows {
+                        first: bounds,
+                        second: (other.min_y, other.max_y),
+                    });
+                }
+            }
         }
-        None
+        Ok(())
     }
 }
 
-pub fn default_numpad_layout() -> Layout<EV_KEY> {
-    fn insert_next_key(vec: &mut Vec<RowItem<EV_KEY>>, right_x: usize, key: EV_KEY) {
-        let margin_x = 50;
-        vec.push(RowItem {
-            left_x: vec.last().unwrap().right_x + margin_x,
-            right_x,
-            item: key,
-        });
+impl<T> Layout<T> {
+    /// The item at the given coordinates. The bounds of rows and items are inclusive on both ends, and
+    /// the margins between them belong to nothing, so touching them (or anything outside of the rows)
+    /// gives None. Since [Layout::validate] rejects overlaps, two items never share a boundary; for a
+    /// layout that wasn't validated, which of the overlapping items is found is unspecified.
+    pub fn get_item(&self, x: usize, y: usize) -> Option<&T> {
+        self.get_row_item(x, y).map(RowItem::item)
+    }
+
+    /// Like [Layout::get_item], but giving the whole item, which also has its hold item.
+    pub fn get_row_item(&self, x: usize, y: usize) -> Option<&RowItem<T>> {
+        // the last row starting at or above y is the only one which can contain it, since they don't overlap
+        let row = self.rows[..self.rows.partition_point(|row| row.min_y <= y)].last()?;
+        if y > row.max_y {
+            return None;
+        }
+        let item = row.items[..row.items.partition_point(|item| item.left_x <= x)].last()?;
+        (x <= item.right_x).then_some(item)
     }
-    fn insert_next_row(vec: &mut Vec<Row<EV_KEY>>, items: Vec<RowItem<EV_KEY>>) {
-        let margin_y = 100;
-        vec.push(Row {
+}
+
+impl<T: Display> Layout<T> {
+    /// Draw the layout as text, for spotting misalignment and gaps in a custom layout. Every row is a
+    /// line starting with its y range, on which every item is drawn as a `[...]` holding its name (as
+    /// much of it as fits), horizontally to scale.
+    pub fn render_ascii(&self) -> String {
+        const COLUMNS: usize = 80;
+        let width = self
+            .size
+            .map(|(width, _)| width)
+            .or_else(|| {
+                self.rows
+                    .iter()
+                    .flat_map(|row| &row.items)
+                    .map(|item| item.right_x)
+                    .max()
+            })
+            .unwrap_or(0)
+            .max(1);
+        let column = |x: usize| x.min(width) * (COLUMNS - 1) / width;
+        let mut text = String::new();
+        for row in &self.rows {
+            let mut line = vec![' '; COLUMNS + 1];
+            for item in &row.items {
+                let start = column(item.left_x);
+                let end = column(item.right_x).max(start + 1);
+                line[start] = '[';
+                line[end] = ']';
+                let name = item.item.to_string();
+                for (cell, character) in line[start + 1..end].iter_mut().zip(name.chars()) {
+                    *cell = character;
+                }
+            }
+            let line: String = line.into_iter().collect();
+            text.push_str(format!("{:>5}..{:<5} {}", row.min_y, row.max_y, line).trim_end());
+            text.push('\n');
+        }
+        text
+    }
+}
+
+/// The gaps the builtin layout leaves between neighbouring keys (`x`) and rows (`y`), which belong to
+/// nothing: wider ones make touching the wrong key less likely, narrower ones missing every key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Margins {
+    pub x: usize,
+    pub y: usize,
+}
+
+impl Margins {
+    /// The gaps between the keys printed on the touchpads this was written for.
+    pub const DEFAULT: Margins = Margins { x: 50, y: 100 };
+}
+
+impl Default for Margins {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The layout printed on the touchpads this was written for, with the numlock key toggling the numpad.
+pub fn default_numpad_layout() -> Layout<Action> {
+    default_numpad_layout_with_margins(Margins::DEFAULT)
+}
+
+/// Like [default_numpad_layout], with other gaps between the keys and the rows. The keys stay centered
+/// where they're printed and grow or shrink by the same amount on every side, down to a single point.
+/// Margins of 0 make neighbouring keys share their edges, which [Layout::validate] rejects.
+pub fn default_numpad_layout_with_margins(margins: Margins) -> Layout<Action> {
+    default_numpad_keys(margins).map(|key| match key {
+        EV_KEY::KEY_NUMLOCK => Action::ToggleActive,
+        key => Action::Key(key),
+    })
+}
+
+/// Move both edges of a range inwards (or outwards if `by` is negative) so that it shrinks by `by`,
+/// half on each side, without turning it inside out.
+fn shrink(range: (usize, usize), by: isize) -> (usize, usize) {
+    let (low, high) = range;
+    let low_by = by.div_euclid(2);
+    let (low, high) = (
+        low.saturating_add_signed(low_by),
+        high.saturating_add_signed(low_by - by),
+    );
+    if low <= high {
+        (low, high)
+    } else {
+        let middle = (range.0 + range.1) / 2;
+        (middle, middle)
+    }
+}
+
+fn default_numpad_keys(margins: Margins) -> Layout<EV_KEY> {
+    use EV_KEY::*;
+    // where the keys are printed; a margin goes from the edge of one key to that of the next, so the gap
+    // of coordinates between them which belong to neither is one less
+    let grid = Grid::new(Rect {
+        left: 330,
+        top: 200,
+        right: 3750,
+        bottom: 2420,
+    })
+    .gap(Margins::DEFAULT.x as u32 - 1, Margins::DEFAULT.y as u32 - 1)
+    // the distances from the left edge of each printed key to that of the next one
+    .column_widths(&[580, 740, 660, 770, 720]);
+    let mut rows = grid
+        .layout(&[
+            &[
+                Some(KEY_7),
+                Some(KEY_8),
+                Some(KEY_9),
+                Some(KEY_SLASH),
+                Some(KEY_NUMLOCK),
+            ],
+            &[
+                Some(KEY_4),
+                Some(KEY_5),
+                Some(KEY_6),
+                Some(KEY_KPASTERISK),
+                Some(KEY_BACKSPACE),
+            ],
+            &[
+                Some(KEY_1),
+                Some(KEY_2),
+                Some(KEY_3),
+                Some(KEY_MINUS),
+                Some(KEY_ENTER),
+            ],
+            &[
+                None,
+                Some(KEY_0),
+                Some(KEY_DOT),
+                Some(KEY_KPPLUS),
+                Some(KEY_ENTER),
+            ],
+        ])
+        .rows;
+    // the 0 key reaches over the margin on its left, up to where the 1 key ends
+    rows[3].items[0].left_x = rows[2].items[0].right_x;
+
+    // the grid is for the default margins; every gap is made up of half of the difference taken from
+    // each side
+    let by_x = margins.x as isize - Margins::DEFAULT.x as isize;
+    let by_y = margins.y as isize - Margins::DEFAULT.y as isize;
+    for row in &mut rows {
+        (row.min_y, row.max_y) = shrink((row.min_y, row.max_y), by_y);
+        for item in &mut row.items {
+            (item.left_x, item.right_x) = shrink((item.left_x, item.right_x), by_x);
+        }
+    }
+    Layout::new(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_item_includes_the_edges_of_items_and_rows() {
+        let layout = default_numpad_layout();
+        // the 7 key goes from (330, 200) to (860, 680)
+        for (x, y) in [(330, 200), (860, 200), (330, 680), (860, 680)] {
+            assert_eq!(layout.get_item(x, y), Some(&Action::Key(EV_KEY::KEY_7)));
+        }
+        // the next key and row start at the first coordinate past the margin
+        assert_eq!(layout.get_item(910, 200), Some(&Action::Key(EV_KEY::KEY_8)));
+        assert_eq!(layout.get_item(330, 780), Some(&Action::Key(EV_KEY::KEY_4)));
+        assert_eq!(
+            layout.get_item(3750, 2420),
+            Some(&Action::Key(EV_KEY::KEY_ENTER))
+        );
+    }
+
+    #[test]
+    fn get_item_finds_nothing_in_the_margins() {
+        let layout = default_numpad_layout();
+        // between the 7 and the 8 key
+        for x in [861, 885, 909] {
+            assert_eq!(layout.get_item(x, 400), None);
+        }
+        // between the first and the second row
+        for y in [681, 730, 779] {
+            assert_eq!(layout.get_item(500, y), None);
+        }
+        // where the margins between keys and rows cross
+        assert_eq!(layout.get_item(885, 730), None);
+    }
+
+    #[test]
+    fn get_item_finds_nothing_outside_of_the_grid() {
+        let layout = default_numpad_layout();
+        for (x, y) in [
+            (0, 0),
+            (329, 200),
+            (330, 199),
+            (3751, 200),
+            (3750, 2421),
+            // left of the 0 key, where the bottom row has no key
+            (500, 2000),
+            (usize::MAX, usize::MAX),
+        ] {
+            assert_eq!(layout.get_item(x, y), None, "at ({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn default_layout_rows_are_where_the_keys_are_printed() {
+        let layout = default_numpad_layout();
+        let rows: Vec<(usize, usize)> = layout
+            .rows()
+            .iter()
+            .map(|row| (row.min_y(), row.max_y()))
+            .collect();
+        assert_eq!(rows, [(200, 680), (780, 1260), (1360, 1840), (1940, 2420)]);
+    }
+
+    #[test]
+    fn default_layout_keys_are_where_they_are_printed() {
+        let layout = default_numpad_layout();
+        let columns = [
+            (330, 860),
+            (910, 1600),
+            (1650, 2260),
+            (2310, 3030),
+            (3080, 3750),
+        ];
+        for row in &layout.rows()[..3] {
+            let items: Vec<(usize, usize)> = row
+                .items()
+                .iter()
+                .map(|item| (item.left_x(), item.right_x()))
+                .collect();
+            assert_eq!(items, columns);
+        }
+        // the 0 key reaches over the margin on its left, up to where the 1 key ends
+        let items: Vec<(usize, usize)> = layout.rows()[3]
+            .items()
+            .iter()
+            .map(|item| (item.left_x(), item.right_x()))
+            .collect();
+        assert_eq!(
             items,
-            max_y: vec.last().unwrap().max_y + margin_y + vec.last().unwrap().max_y
-                - vec.last().unwrap().min_y,
-            min_y: vec.last().unwrap().max_y + margin_y,
+            [(860, 1600), (1650, 2260), (2310, 3030), (3080, 3750)]
+        );
+        layout.validate().unwrap();
+    }
+
+    /// Every key's (left_x, right_x, min_y, max_y), from the top left to the bottom right.
+    fn key_bounds(layout: &Layout<Action>) -> Vec<(usize, usize, usize, usize)> {
+        layout
+            .rows()
+            .iter()
+            .flat_map(|row| {
+                row.items()
+                    .iter()
+                    .map(|item| (item.left_x(), item.right_x(), row.min_y(), row.max_y()))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn wider_margins_shrink_every_key_on_both_sides() {
+        let default = key_bounds(&default_numpad_layout());
+        let wider = key_bounds(&default_numpad_layout_with_margins(Margins {
+            x: 150,
+            y: 300,
+        }));
+        assert_eq!(default.len(), wider.len());
+        for (key, shrunk) in default.iter().zip(&wider) {
+            assert_eq!(
+                *shrunk,
+                (key.0 + 50, key.1 - 50, key.2 + 100, key.3 - 100),
+                "{:?}",
+                key
+            );
+        }
+    }
+
+    #[test]
+    fn narrower_margins_grow_every_key_on_both_sides() {
+        let default = key_bounds(&default_numpad_layout());
+        let narrower = key_bounds(&default_numpad_layout_with_margins(Margins {
+            x: 10,
+            y: 20,
+        }));
+        for (key, grown) in default.iter().zip(&narrower) {
+            assert_eq!(*grown, (key.0 - 20, key.1 + 20, key.2 - 40, key.3 + 40));
+        }
+        default_numpad_layout_with_margins(Margins { x: 10, y: 20 })
+            .validate()
+            .unwrap();
+    }
+
+    #[test]
+    fn margins_wider_than_a_key_leave_its_middle() {
+        let layout = default_numpad_layout_with_margins(Margins { x: 2000, y: 2000 });
+        // the 7 key goes from 330 to 860 and from 200 to 680 with the default margins
+        assert_eq!(key_bounds(&layout)[0], (595, 595, 440, 440));
+    }
+
+    #[test]
+    fn mirroring_twice_gives_back_the_layout() {
+        let layout = default_numpad_layout();
+        assert_eq!(layout.clone().mirror_x(4000).mirror_x(4000), layout);
+        // a width the layout only just fits in
+        assert_eq!(layout.clone().mirror_x(3750).mirror_x(3750), layout);
+    }
+
+    #[test]
+    fn mirroring_moves_items_to_the_other_side() {
+        let layout = default_numpad_layout().mirror_x(4000);
+        // the numlock key goes from 3080 to 3750, so it ends up from 250 to 920 on the left
+        let first = &layout.rows()[0].items()[0];
+        assert_eq!((first.left_x(), first.right_x()), (250, 920));
+        assert_eq!(first.item(), &Action::ToggleActive);
+        assert_eq!(layout.get_item(300, 400), Some(&Action::ToggleActive));
+        assert_eq!(
+            layout.get_item(3500, 400),
+            Some(&Action::Key(EV_KEY::KEY_7))
+        );
+    }
+
+    #[test]
+    fn mirroring_twice_moves_items_beyond_the_width_to_its_edge() {
+        let layout = Layout::new(vec![Row::new(
+            0,
+            100,
+            vec![RowItem::new(100, 200, 'a'), RowItem::new(500, 600, 'b')],
+        )]);
+        let mirrored = layout.mirror_x(400).mirror_x(400);
+        let items: Vec<(usize, usize)> = mirrored.rows()[0]
+            .items()
+            .iter()
+            .map(|item| (item.left_x(), item.right_x()))
+            .collect();
+        assert_eq!(items, [(100, 200), (400, 400)]);
+    }
+
+    #[test]
+    fn render_ascii_draws_the_default_layout() {
+        let expected = concat!(
+            "  200..680         [KEY_7      ][KEY_8        ][KEY_9       ][KEY_SLASH     ][TOGGLE_ACTIVE ]\n",
+            "  780..1260        [KEY_4      ][KEY_5        ][KEY_6       ][KEY_KPASTERISK][KEY_BACKSPACE ]\n",
+            " 1360..1840        [KEY_1      ][KEY_2        ][KEY_3       ][KEY_MINUS     ][KEY_ENTER     ]\n",
+            " 1940..2420                    [KEY_0         ][KEY_DOT     ][KEY_KPPLUS    ][KEY_ENTER     ]\n",
+        );
+        assert_eq!(default_numpad_layout().render_ascii(), expected);
+    }
+
+    #[test]
+    fn render_ascii_cuts_names_which_dont_fit() {
+        let layout = Layout::new(vec![Row::new(
+            0,
+            10,
+            vec![RowItem::new(0, 3, Action::ToggleActive)],
+        )])
+        .with_size(79, 10);
+        assert_eq!(layout.render_ascii(), "    0..10    [TO]\n");
+    }
+
+    /// What get_row_item finds, by going through every item.
+    fn linear_lookup<T>(layout: &Layout<T>, x: usize, y: usize) -> Option<&RowItem<T>> {
+        layout
+            .rows()
+            .iter()
+            .filter(|row| row.min_y() <= y && y <= row.max_y())
+            .flat_map(Row::items)
+            .find(|item| item.left_x() <= x && x <= item.right_x())
+    }
+
+    #[test]
+    fn binary_search_finds_the_same_items_as_a_linear_lookup() {
+        let layouts = [
+            default_numpad_layout(),
+            default_numpad_layout().mirror_x(4000),
+            default_numpad_layout_with_margins(Margins { x: 1, y: 1 }),
+        ];
+        for layout in &layouts {
+            for y in (0..2600).step_by(7) {
+                for x in (0..4000).step_by(7) {
+                    assert_eq!(
+                        layout.get_row_item(x, y),
+                        linear_lookup(layout, x, y),
+                        "at ({}, {})",
+                        x,
+                        y
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn binary_search_is_exact_at_every_edge() {
+        let layout = default_numpad_layout();
+        let edges = layout.rows().iter().flat_map(|row| {
+            row.items().iter().flat_map(move |item| {
+                [item.left_x(), item.right_x()]
+                    .into_iter()
+                    .flat_map(move |x| [row.min_y(), row.max_y()].into_iter().map(move |y| (x, y)))
+            })
+        });
+        for (x, y) in edges {
+            for (x, y) in [(x - 1, y), (x, y), (x + 1, y), (x, y - 1), (x, y + 1)] {
+                assert_eq!(layout.get_row_item(x, y), linear_lookup(&layout, x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn fit_to_moves_and_stretches_the_items() {
+        let layout = Layout::new(vec![
+            Row::new(100, 200, vec![RowItem::new(100, 200, 'a')]),
+            Row::new(300, 400, vec![RowItem::new(200, 300, 'b')]),
+        ])
+        .fit_to(Rect {
+            left: 1000,
+            top: 2000,
+            right: 1400,
+            bottom: 2600,
         });
+        assert_eq!(
+            layout.bounds(),
+            Some(Rect {
+                left: 1000,
+                top: 2000,
+                right: 1400,
+                bottom: 2600
+            })
+        );
+        assert_eq!(layout.get_item(1200, 2200), Some(&'a'));
+        assert_eq!(layout.get_item(1201, 2400), Some(&'b'));
+    }
+
+    #[test]
+    fn fit_to_moves_empty_rows_outside_of_the_bounds_along() {
+        let layout = Layout::new(vec![
+            Row::new(0, 50, Vec::new()),
+            Row::new(100, 200, vec![RowItem::new(100, 200, 'a')]),
+            Row::new(300, 400, Vec::new()),
+        ]);
+        let rows = |layout: &Layout<char>| -> Vec<(usize, usize)> {
+            layout
+                .rows()
+                .iter()
+                .map(|row| (row.min_y(), row.max_y()))
+                .collect()
+        };
+        let area = |top, bottom| Rect {
+            left: 0,
+            top,
+            right: 100,
+            bottom,
+        };
+        let moved = layout.clone().fit_to(area(1000, 1100));
+        assert_eq!(rows(&moved), [(900, 950), (1000, 1100), (1200, 1300)]);
+        // the row above can't go past 0
+        let shrunk = layout.fit_to(area(10, 20));
+        assert_eq!(rows(&shrunk), [(0, 5), (10, 20), (30, 40)]);
+    }
+
+    #[test]
+    fn grid_cells_tile_the_area_without_overlapping() {
+        let area = Rect {
+            left: 100,
+            top: 50,
+            right: 1099,
+            bottom: 349,
+        };
+        for gap in [0, 1, 7, 10] {
+            let items: [&[Option<usize>]; 3] = [
+                &[Some(0), Some(1), Some(2), Some(3)],
+                &[Some(4), Some(5), Some(6), Some(7)],
+                &[Some(8), Some(9), Some(10), Some(11)],
+            ];
+            let layout = Layout::grid(&items, area, gap);
+            layout.validate().unwrap();
+            assert_eq!(layout.bounds(), Some(area));
+            // every coordinate in the area is in exactly one cell or in a gap next to one
+            let rows = layout.rows();
+            for pair in rows.windows(2) {
+                assert_eq!(pair[1].min_y() - pair[0].max_y(), gap as usize + 1);
+            }
+            for row in rows {
+                let items = row.items();
+                assert_eq!(items.len(), 4);
+                for pair in items.windows(2) {
+                    assert_eq!(pair[1].left_x() - pair[0].right_x(), gap as usize + 1);
+                }
+            }
+            // and the cells are as large as each other, give or take one for rounding
+            let widths: Vec<usize> = rows[0]
+                .items()
+                .iter()
+                .map(|item| item.right_x() - item.left_x())
+                .collect();
+            let (min, max) = (widths.iter().min().unwrap(), widths.iter().max().unwrap());
+            assert!(max - min <= 1, "{:?}", widths);
+        }
+    }
+
+    #[test]
+    fn grid_leaves_empty_cells_out() {
+        let area = Rect {
+            left: 0,
+            top: 0,
+            right: 299,
+            bottom: 199,
+        };
+        let layout = Layout::grid(&[&[Some('a'), None, Some('c')], &[Some('d')]], area, 0);
+        assert_eq!(layout.get_item(50, 50), Some(&'a'));
+        assert_eq!(layout.get_item(150, 50), None);
+        assert_eq!(layout.get_item(250, 50), Some(&'c'));
+        assert_eq!(layout.get_item(50, 150), Some(&'d'));
+        assert_eq!(layout.get_item(150, 150), None);
+    }
+
+    #[test]
+    fn grid_columns_follow_their_widths() {
+        let area = Rect {
+            left: 0,
+            top: 0,
+            right: 399,
+            bottom: 99,
+        };
+        let layout = Grid::new(area)
+            .gap(10, 0)
+            .column_widths(&[1, 3])
+            .layout(&[&[Some('a'), Some('b'), Some('c')]]);
+        let items: Vec<(usize, usize)> = layout.rows()[0]
+            .items()
+            .iter()
+            .map(|item| (item.left_x(), item.right_x()))
+            .collect();
+        assert_eq!(items, [(0, 91), (102, 399)]);
+    }
+
+    fn key(left_x: usize, right_x: usize, key: EV_KEY) -> RowItem<Action> {
+        RowItem::new(left_x, right_x, Action::Key(key))
+    }
+
+    #[test]
+    fn validate_rejects_overlapping_items() {
+        let layout = Layout::new(vec![Row::new(
+            0,
+            100,
+            vec![key(0, 100, EV_KEY::KEY_7), key(100, 200, EV_KEY::KEY_8)],
+        )]);
+        let error = layout.validate().unwrap_err();
+        assert!(
+            matches!(
+                &error,
+                LayoutError::OverlappingItems { row: (0, 100), first, second }
+                    if first == "KEY_7" && second == "KEY_8"
+            ),
+            "{:?}",
+            error
+        );
+        assert_eq!(
+            error.to_string(),
+            "KEY_7 and KEY_8 overlap in the row from y 0 to 100"
+        );
+    }
+
+    #[test]
+    fn validate_rejects_overlapping_rows() {
+        // in the file, the lower row comes first
+        let layout = Layout::new(vec![
+            Row::new(90, 200, vec![key(0, 100, EV_KEY::KEY_4)]),
+            Row::new(0, 100, vec![key(0, 100, EV_KEY::KEY_7)]),
+        ]);
+        assert!(matches!(
+            layout.validate(),
+            Err(LayoutError::OverlappingRows {
+                first: (0, 100),
+                second: (90, 200),
+            })
+        ));
+    }
+
+    #[test]
+    fn validate_rejects_inverted_bounds() {
+        let layout = Layout::new(vec![Row::new(0, 100, vec![key(200, 100, EV_KEY::KEY_7)])]);
+        let error = layout.validate().unwrap_err();
+        assert!(
+            matches!(
+                &error,
+                LayoutError::InvertedItemBounds { row: (0, 100), item, left_x: 200, right_x: 100 }
+                    if item == "KEY_7"
+            ),
+            "{:?}",
+            error
+        );
+        let layout = Layout::new(vec![Row::new(100, 0, vec![key(0, 100, EV_KEY::KEY_7)])]);
+        assert!(matches!(
+            layout.validate(),
+            Err(LayoutError::InvertedRowBounds {
+                min_y: 100,
+                max_y: 0,
+            })
+        ));
+    }
+
+    #[test]
+    fn validate_rejects_rows_outside_of_the_size() {
+        let rows = || vec![Row::new(0, 100, vec![key(0, 100, EV_KEY::KEY_7)])];
+        Layout::new(rows()).with_size(100, 100).validate().unwrap();
+        for size in [(99, 100), (100, 99)] {
+            assert!(matches!(
+                Layout::new(rows()).with_size(size.0, size.1).validate(),
+                Err(LayoutError::OutOfBounds { row: (0, 100), size: s }) if s == size
+            ));
+        }
+    }
+
+    #[test]
+    fn get_item_on_an_empty_layout_finds_nothing() {
+        let layout: Layout<Action> = Layout::new(Vec::new());
+        assert_eq!(layout.get_item(0, 0), None);
+        let layout = Layout::new(vec![Row::<Action>::new(0, 100, Vec::new())]);
+        assert_eq!(layout.get_item(50, 50), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trips_the_default_layout() {
+        let layout = default_numpad_layout().with_size(3900, 2600);
+        let text = toml::to_string(&layout).unwrap();
+        assert_eq!(toml::from_str::<Layout<Action>>(&text).unwrap(), layout);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_sorts_and_validates_layouts() {
+        let text = r#"
+            [[rows]]
+            min_y = 200
+            max_y = 300
+            items = [
+                { left_x = 200, right_x = 300, key = "KEY_2" },
+                { left_x = 0, right_x = 100, key = "KEY_1" },
+            ]
+
+            [[rows]]
+            min_y = 0
+            max_y = 100
+            items = [{ left_x = 0, right_x = 100, key = "KEY_7" }]
+        "#;
+        let layout: Layout<Action> = toml::from_str(text).unwrap();
+        assert_eq!(layout.get_item(50, 50), Some(&Action::Key(EV_KEY::KEY_7)));
+        assert_eq!(layout.get_item(50, 250), Some(&Action::Key(EV_KEY::KEY_1)));
+        assert_eq!(layout.get_item(250, 250), Some(&Action::Key(EV_KEY::KEY_2)));
+
+        let overlapping = text.replace("min_y = 200", "min_y = 50");
+        let error = toml::from_str::<Layout<Action>>(&overlapping).unwrap_err();
+        assert!(error.to_string().contains("overlap"), "{}", error);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_uses_the_layout_file_format() {
+        let layout = default_numpad_layout().with_size(3900, 2600);
+        let text = toml::to_string(&layout).unwrap();
+        assert_eq!(crate::config::parse_layout(&text).unwrap(), layout);
+        let text = crate::config::layout_to_toml(&layout);
+        assert_eq!(toml::from_str::<Layout<Action>>(&text).unwrap(), layout);
     }
-    let mut rows = Vec::new();
-    let mut items = vec![RowItem {
-        left_x: 330,
-        right_x: 860,
-        item: EV_KEY::KEY_7,
-    }];
-    let items_ref = &mut items;
-    insert_next_key(items_ref, 1600, EV_KEY::KEY_8);
-    insert_next_key(items_ref, 2260, EV_KEY::KEY_9);
-    insert_next_key(items_ref, 3030, EV_KEY::KEY_SLASH);
-    insert_next_key(items_ref, 3750, EV_KEY::KEY_NUMLOCK);
-
-    let first_row = Row {
-        items,
-        min_y: 200,
-        max_y: 680,
-    };
-    rows.push(first_row);
-
-    let mut items = vec![RowItem {
-        left_x: 330,
-        right_x: 860,
-        item: EV_KEY::KEY_4,
-    }];
-    let items_ref = &mut items;
-    insert_next_key(items_ref, 1600, EV_KEY::KEY_5);
-    insert_next_key(items_ref, 2260, EV_KEY::KEY_6);
-    insert_next_key(items_ref, 3030, EV_KEY::KEY_KPASTERISK);
-    insert_next_key(items_ref, 3750, EV_KEY::KEY_BACKSPACE);
-    insert_next_row(&mut rows, items);
-
-    let mut items = vec![RowItem {
-        left_x: 330,
-        right_x: 860,
-        item: EV_KEY::KEY_1,
-    }];
-    let items_ref = &mut items;
-    insert_next_key(items_ref, 1600, EV_KEY::KEY_2);
-    insert_next_key(items_ref, 2260, EV_KEY::KEY_3);
-    insert_next_key(items_ref, 3030, EV_KEY::KEY_MINUS);
-    insert_next_key(items_ref, 3750, EV_KEY::KEY_ENTER);
-    insert_next_row(&mut rows, items);
-
-    let mut items = vec![RowItem {
-        left_x: 860,
-        right_x: 1600,
-        item: EV_KEY::KEY_0,
-    }];
-    let items_ref = &mut items;
-    insert_next_key(items_ref, 2260, EV_KEY::KEY_DOT);
-    insert_next_key(items_ref, 3030, EV_KEY::KEY_KPPLUS);
-    insert_next_key(items_ref, 3750, EV_KEY::KEY_ENTER);
-    insert_next_row(&mut rows, items);
-
-    Layout { rows }
 }
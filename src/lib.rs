@@ -1,4 +1,14 @@
+pub mod config;
+#[cfg(feature = "dbus")]
+pub mod dbus;
 pub mod dev;
+pub mod discovery;
+pub mod haptics;
 pub mod key_simulation;
 pub mod layout;
+#[cfg(feature = "logging")]
+pub mod logging;
+#[cfg(feature = "notify")]
+pub mod notification;
 pub mod numpad_light;
+pub mod output;
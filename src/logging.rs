@@ -0,0 +1,8 @@
+use tracing_subscriber::EnvFilter;
+
+/// Print the driver's logs to stderr (which ends up in the journal when running as a service).
+/// The level defaults to info and can be changed with `RUST_LOG`, e.g. `RUST_LOG=numpad_driver=trace`.
+pub fn init() {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    tracing_subscriber::fmt().with_env_filter(filter).init();
+}
@@ -1,5 +1,134 @@
-use numpad_driver::dev::NumberPad;
-fn main() {
-    let mut number_pad = NumberPad::new().unwrap();
-    number_pad.enter_input_loop().unwrap();
+use std::{error::Error, path::PathBuf, process::ExitCode};
+
+use clap::Parser;
+use numpad_driver::{config, dev::NumberPad, numpad_light::MAX_BRIGHTNESS};
+
+/// Numpad driver for ASUS touchpads with a numpad printed on them
+#[derive(Parser)]
+struct Args {
+    /// Brightness to start with, from 0 to 7; defaults to the light's current brightness, or the last
+    /// used one if it can't tell
+    #[arg(
+        long,
+        conflicts_with = "brightness_percent",
+        value_parser = clap::value_parser!(u8).range(0..=MAX_BRIGHTNESS as i64)
+    )]
+    brightness: Option<u8>,
+    /// Brightness to start with as a percentage, mapped to the light's levels with the gamma
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100))]
+    brightness_percent: Option<u8>,
+    /// Gamma to map brightness percentages with, e.g. 2.2 for steps which look even;
+    /// defaults to brightness_gamma from the config, or 1.0 (linear)
+    #[arg(long)]
+    brightness_gamma: Option<f64>,
+    /// Layout file to use instead of ~/.config/numpad_driver/layout.toml
+    #[arg(long)]
+    layout: Option<PathBuf>,
+    /// Don't control the numpad's light
+    #[arg(long)]
+    no_light: bool,
+    /// Handle touches and control the light, but never grab the touchpad or press any keys
+    #[arg(long)]
+    passthrough: bool,
+    /// Print where each touch is lifted and which key it resolves to, without grabbing the touchpad,
+    /// pressing anything or controlling the light
+    #[arg(long)]
+    dry_run: bool,
+    /// Print every event read from the touchpad, with its time, code and value, before handling it
+    #[arg(long)]
+    trace_raw: bool,
+    /// Print the layout as a grid and exit
+    #[arg(long)]
+    print_layout: bool,
+    /// Print every touchpad event and the key it maps to, without grabbing the touchpad or pressing anything
+    #[arg(long)]
+    dump_events: bool,
+    /// Fit the layout to two taps on the numpad's corners and save it as the layout file
+    #[arg(long)]
+    calibrate: bool,
+    /// Serve a D-Bus interface for toggling the numpad and changing its brightness on this bus
+    #[cfg(feature = "dbus")]
+    #[arg(long, value_enum)]
+    dbus: Option<numpad_driver::dbus::Bus>,
+    /// Pause the numpad while the session is locked, as logind reports it on the system bus
+    #[cfg(feature = "dbus")]
+    #[arg(long)]
+    pause_when_locked: bool,
+    /// Restore the touchpad and the light after the system wakes up from a suspend, as logind reports
+    /// it on the system bus
+    #[cfg(feature = "dbus")]
+    #[arg(long)]
+    restore_after_sleep: bool,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    #[cfg(feature = "logging")]
+    numpad_driver::logging::init();
+    let mut builder = NumberPad::builder()
+        .light_enabled(!args.no_light)
+        .passthrough(args.passthrough)
+        .dry_run(args.dry_run)
+        .trace_raw(args.trace_raw);
+    if let Some(layout_path) = &args.layout {
+        builder = builder.layout_path(layout_path);
+    }
+    if let Some(brightness) = args.brightness {
+        builder = builder.initial_brightness(brightness);
+    }
+    if let Some(percent) = args.brightness_percent {
+        builder = builder.initial_brightness_percent(percent);
+    }
+    if let Some(gamma) = args.brightness_gamma {
+        builder = builder.brightness_gamma(gamma);
+    }
+    if args.print_layout {
+        print!("{}", builder.main_layout()?.render_ascii());
+        return Ok(());
+    }
+    if args.calibrate {
+        let mut number_pad = builder.light_enabled(false).build()?;
+        let layout = number_pad.calibrate()?;
+        // save it where it gets loaded from
+        let path = args
+            .layout
+            .or_else(config::layout_path)
+            .ok_or("couldn't tell where the layout file goes")?;
+        config::save_layout(&layout, &path)?;
+        println!("Saved the layout to {}", path.display());
+        return Ok(());
+    }
+    let mut number_pad = builder.build()?;
+    // kept around so that the interface is served until we're done
+    #[cfg(feature = "dbus")]
+    let _connection = args
+        .dbus
+        .map(|bus| numpad_driver::dbus::serve(&mut number_pad, bus))
+        .transpose()?;
+    #[cfg(feature = "dbus")]
+    let _lock_connection = args
+        .pause_when_locked
+        .then(|| numpad_driver::dbus::pause_while_locked(&mut number_pad))
+        .transpose()?;
+    #[cfg(feature = "dbus")]
+    let _sleep_connection = args
+        .restore_after_sleep
+        .then(|| numpad_driver::dbus::restore_after_sleep(&mut number_pad))
+        .transpose()?;
+    if args.dump_events {
+        number_pad.dump_events()?;
+    } else {
+        number_pad.enter_input_loop()?;
+    }
+    Ok(())
 }
@@ -0,0 +1,40 @@
+//! A desktop notification showing the brightness when it's changed on the numpad itself, since the light
+//! alone makes it hard to tell how many levels are left.
+
+use notify_rust::{Hint, Notification, Timeout, error::Result};
+
+/// How long the notification stays up after the last change.
+const NOTIFICATION_TIMEOUT: Timeout = Timeout::Milliseconds(1500);
+
+/// The brightness notification, which replaces the previous one instead of stacking up on every change.
+#[derive(Debug, Default)]
+pub struct BrightnessNotification {
+    /// The id of the notification which was shown last, if any.
+    id: Option<u32>,
+}
+
+impl BrightnessNotification {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Show the brightness level out of the maximum, replacing the last notification if it's still up.
+    pub fn show(&mut self, brightness: u8, max_brightness: u8) -> Result<()> {
+        let percent = brightness as i32 * 100 / max_brightness.max(1) as i32;
+        let mut notification = Notification::new();
+        notification
+            .appname("numpad_driver")
+            .summary("Numpad brightness")
+            .body(&format!("{}/{}", brightness, max_brightness))
+            .icon("keyboard-brightness")
+            // shown as a bar by the notification servers which support it
+            .hint(Hint::CustomInt("value".to_string(), percent))
+            .hint(Hint::Transient(true))
+            .timeout(NOTIFICATION_TIMEOUT);
+        if let Some(id) = self.id {
+            notification.id(id);
+        }
+        self.id = Some(notification.show()?.id());
+        Ok(())
+    }
+}
@@ -1,53 +1,719 @@
-use std::io::ErrorKind;
+use std::time::{Duration, Instant};
 
-use i2cdev::{core::I2CDevice, linux::LinuxI2CDevice};
+use i2cdev::{
+    core::{I2CDevice, I2CMessage, I2CTransfer},
+    linux::{LinuxI2CDevice, LinuxI2CError, LinuxI2CMessage},
+};
 
-pub struct NumpadLight {
-    dev: LinuxI2CDevice,
+/// The numpad's light, which is talked to through `D`: the I2C device, unless it's e.g. a
+/// [RecordingTransport].
+pub struct NumpadLight<D: LightTransport = LinuxI2CDevice> {
+    dev: D,
+    model: &'static LightModel,
+    /// The highest brightness level, from the model.
+    max_brightness: u8,
+    /// The last brightness level that was written, if any.
+    brightness: Option<u8>,
+    /// Whether the light was turned on, and not off since; it's turned off when this is dropped.
+    is_on: bool,
+    fade: Option<Fade>,
+    /// See [NumpadLight::set_retries].
+    retries: u32,
+    /// See [NumpadLight::set_gamma].
+    gamma: f64,
 }
 
-type Result<T> = std::result::Result<T, i2cdev::linux::LinuxI2CError>;
+/// What the light is doing, see [NumpadLight::status].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LightStatus {
+    /// See [NumpadLight::is_on].
+    pub on: bool,
+    /// The brightness level which was last written, or 0 if none was.
+    pub level: u8,
+    /// See [NumpadLight::max_brightness].
+    pub max: u8,
+}
+
+/// A gradual change of the brightness, see [NumpadLight::fade_to].
+#[derive(Debug, Clone, Copy)]
+struct Fade {
+    from: u8,
+    to: u8,
+    start: Instant,
+    duration: Duration,
+}
+
+impl Fade {
+    fn steps(&self) -> u32 {
+        self.from.abs_diff(self.to) as u32
+    }
+
+    /// The level the light should be at, at the given time.
+    fn level_at(&self, now: Instant) -> u8 {
+        let progress = ((now - self.start).as_secs_f64() / self.duration.as_secs_f64()).min(1.0);
+        let steps_done = (self.steps() as f64 * progress).round() as u8;
+        if self.to > self.from {
+            self.from + steps_done
+        } else {
+            self.from - steps_done
+        }
+    }
+
+    /// When the level is next due to change after being at `level`; each step happens halfway between
+    /// the times at which the exact brightness would be at the levels around it.
+    fn next_step(&self, level: u8) -> Instant {
+        let steps_done = self.from.abs_diff(level) as f64;
+        let progress = ((steps_done + 0.5) / self.steps() as f64).min(1.0);
+        self.start + self.duration.mul_f64(progress)
+    }
+}
+
+type Result<T> = std::result::Result<T, LinuxI2CError>;
+
+/// What the commands for the light are written to and its answers read from.
+pub trait LightTransport {
+    fn write(&mut self, data: &[u8]) -> Result<()>;
+    /// Write `command` and fill `answer` with what the device answers, in one combined transaction so
+    /// that nothing else can use the bus in between.
+    fn write_read(&mut self, command: &[u8], answer: &mut [u8]) -> Result<()>;
+}
+
+impl LightTransport for LinuxI2CDevice {
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        I2CDevice::write(self, data)
+    }
+
+    fn write_read(&mut self, command: &[u8], answer: &mut [u8]) -> Result<()> {
+        let mut messages = [
+            LinuxI2CMessage::write(command),
+            LinuxI2CMessage::read(answer),
+        ];
+        self.transfer(&mut messages).map(drop)
+    }
+}
+
+/// A transport which keeps every command written to it instead of sending it anywhere, so that what a
+/// [NumpadLight] writes can be checked without the hardware.
+#[derive(Debug, Default, Clone)]
+pub struct RecordingTransport {
+    /// The commands in the order they were written.
+    pub writes: Vec<Vec<u8>>,
+    /// What reads are answered with, padded with zeros or cut to the length which is read. The command
+    /// a read is for is kept in `writes` too.
+    pub answer: Vec<u8>,
+}
+
+impl LightTransport for RecordingTransport {
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.writes.push(data.to_vec());
+        Ok(())
+    }
+
+    fn write_read(&mut self, command: &[u8], answer: &mut [u8]) -> Result<()> {
+        self.writes.push(command.to_vec());
+        answer.fill(0);
+        let length = answer.len().min(self.answer.len());
+        answer[..length].copy_from_slice(&self.answer[..length]);
+        Ok(())
+    }
+}
 
+/// The highest brightness level of the default model, which is also what's assumed without a light.
 pub const MAX_BRIGHTNESS: u8 = 7;
 
+/// The I2C address of the light on the models this was written for.
+pub const DEFAULT_ADDRESS: u16 = 0x38;
+
+/// How many times a write is retried by default while the bus is busy.
+pub const DEFAULT_RETRIES: u32 = 3;
+
+/// The gamma percentages are mapped to brightness levels with by default, which maps them linearly.
+pub const DEFAULT_GAMMA: f64 = 1.0;
+
+/// A gamma which roughly matches how differences in brightness are perceived, so that the steps between
+/// percentages look about even, see [NumpadLight::set_gamma].
+pub const PERCEPTUAL_GAMMA: f64 = 2.2;
+
+/// How long to wait before the first retry, doubling for every further one.
+const RETRY_DELAY: Duration = Duration::from_millis(2);
+
+/// The commands a model's light understands. Every command is the prefix, then a value, then the suffix.
+#[derive(Debug, PartialEq, Eq)]
+pub struct LightModel {
+    /// The name the model is selected by in the config.
+    pub name: &'static str,
+    pub prefix: &'static [u8],
+    pub suffix: &'static [u8],
+    pub turn_on: u8,
+    pub turn_off: u8,
+    /// The value for brightness level 0; the other levels follow it.
+    pub brightness_offset: u8,
+    /// The highest brightness level, so there are one more levels than this.
+    pub max_brightness: u8,
+    /// The command asking for the current brightness, or None if the light can't report it.
+    pub read_command: Option<&'static [u8]>,
+    /// What the answer to the read command starts with, before the brightness value.
+    pub read_answer: &'static [u8],
+}
+
+/// The models whose lights are known, starting with the default one.
+pub static LIGHT_MODELS: &[LightModel] = &[LightModel {
+    name: "strix_16_2024",
+    // a HID feature report set over I2C
+    prefix: &[
+        0x05, 0x00, 0x3d, 0x03, 0x06, 0x00, 0x07, 0x00, 0x0d, 0x14, 0x03,
+    ],
+    suffix: &[0xad],
+    turn_on: 1,
+    turn_off: 0,
+    brightness_offset: 65,
+    max_brightness: MAX_BRIGHTNESS,
+    // a GET_REPORT request for the same feature report, as in section 7.2.2 of Microsoft's HID over
+    // I2C protocol specification: the command register (0x0005), the report type and ID (feature, 0x0d),
+    // the GET_REPORT opcode (0x02) and the data register (0x0006) the answer is read from
+    read_command: Some(&[0x05, 0x00, 0x3d, 0x02, 0x06, 0x00]),
+    // a report is answered with two bytes of its length, then the report as the prefix writes it
+    read_answer: &[0x07, 0x00, 0x0d, 0x14, 0x03],
+}];
+
+impl LightModel {
+    pub fn by_name(name: &str) -> Option<&'static Self> {
+        LIGHT_MODELS.iter().find(|model| model.name == name)
+    }
+
+    pub fn default_model() -> &'static Self {
+        &LIGHT_MODELS[0]
+    }
+}
+
+/// Whether the I2C address is a valid 7 bit address, excluding the reserved ones.
+pub fn is_valid_address(address: u16) -> bool {
+    (0x08..=0x77).contains(&address)
+}
+
+/// Whether the gamma can map percentages to brightness levels, i.e. it's positive and finite.
+pub fn is_valid_gamma(gamma: f64) -> bool {
+    gamma.is_finite() && gamma > 0.0
+}
+
 impl NumpadLight {
-    const TURN_OFF: u8 = 0;
-    const TURN_ON: u8 = 1;
-    const BRIGHTNESS_OFFSET: u8 = 65;
-    pub fn new(i2c_id: u32) -> Result<Self> {
-        let slave_addr = 0x38;
+    /// Connect to the light of the given model at the given address on the I2C bus, see [is_valid_address].
+    pub fn new(i2c_id: u32, slave_addr: u16, model: &'static LightModel) -> Result<Self> {
+        if !is_valid_address(slave_addr) {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("invalid i2c address {:#04x}", slave_addr),
+            ))?;
+        }
         // we need to force it bc the driver is constatnly busy. This should be fine since the current driver doesn't even touch the brightness anyways.
         let dev = unsafe { LinuxI2CDevice::force_new(format!("/dev/i2c-{}", i2c_id), slave_addr)? };
-        Ok(Self { dev })
+        Ok(Self::with_transport(dev, model))
+    }
+
+    /// Connect to the default model's light at [DEFAULT_ADDRESS].
+    pub fn with_default_address(i2c_id: u32) -> Result<Self> {
+        Self::new(i2c_id, DEFAULT_ADDRESS, LightModel::default_model())
+    }
+}
+
+impl<D: LightTransport> NumpadLight<D> {
+    /// Talk to a light of the given model through the transport, without writing anything yet.
+    pub fn with_transport(transport: D, model: &'static LightModel) -> Self {
+        Self {
+            dev: transport,
+            model,
+            max_brightness: model.max_brightness,
+            brightness: None,
+            is_on: false,
+            fade: None,
+            retries: DEFAULT_RETRIES,
+            gamma: DEFAULT_GAMMA,
+        }
+    }
+
+    /// The transport the light is talked to through, e.g. for looking at what a
+    /// [RecordingTransport] recorded.
+    pub fn transport(&self) -> &D {
+        &self.dev
+    }
+
+    pub fn transport_mut(&mut self) -> &mut D {
+        &mut self.dev
+    }
+
+    /// Set how many times a write is retried, after a short and growing delay, when it fails because the
+    /// bus is busy, before the error is returned. The default is [DEFAULT_RETRIES].
+    pub fn set_retries(&mut self, retries: u32) {
+        self.retries = retries;
+    }
+
+    /// Set the gamma of the mapping between percentages and brightness levels, see
+    /// [NumpadLight::set_brightness_percent]; 1.0 maps them linearly, and [PERCEPTUAL_GAMMA] makes the
+    /// steps look even. The default is [DEFAULT_GAMMA].
+    /// Returns an error of kind [std::io::ErrorKind::InvalidInput] if the gamma isn't valid, see
+    /// [is_valid_gamma].
+    pub fn set_gamma(&mut self, gamma: f64) -> Result<()> {
+        if !is_valid_gamma(gamma) {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("invalid gamma {}, it has to be positive", gamma),
+            ))?;
+        }
+        self.gamma = gamma;
+        Ok(())
     }
 
     fn write(&mut self, num: u8) -> Result<()> {
-        self.dev.write(&[
-            0x05, 0x00, 0x3d, 0x03, 0x06, 0x00, 0x07, 0x00, 0x0d, 0x14, 0x03, num, 0xad,
-        ])
+        let command: Vec<u8> = [self.model.prefix, &[num], self.model.suffix].concat();
+        self.write_command(&command)
+    }
+
+    fn write_command(&mut self, command: &[u8]) -> Result<()> {
+        self.retry(|dev| dev.write(command))
+    }
+
+    /// Do `transfer`, retrying it while the bus is busy.
+    fn retry(&mut self, mut transfer: impl FnMut(&mut D) -> Result<()>) -> Result<()> {
+        let mut delay = RETRY_DELAY;
+        for _ in 0..self.retries {
+            match transfer(&mut self.dev) {
+                // the touchpad's driver uses the bus too, so it's usually free again right away
+                Err(error) if is_busy(&error) => {
+                    std::thread::sleep(delay);
+                    delay *= 2;
+                }
+                result => return result,
+            }
+        }
+        transfer(&mut self.dev)
+    }
+
+    /// Read the brightness level back from the device, as answered to the model's read command.
+    /// If the model has no read command, or the device answers with anything else, which is the case if
+    /// the light is turned off, an error of kind [std::io::ErrorKind::Unsupported] is returned.
+    pub fn read_brightness(&mut self) -> Result<u8> {
+        let unsupported =
+            |message: String| std::io::Error::new(std::io::ErrorKind::Unsupported, message);
+        let Some(read_command) = self.model.read_command else {
+            Err(unsupported(format!(
+                "the {} light can't report its brightness",
+                self.model.name
+            )))?
+        };
+        let mut answer = vec![0; self.model.read_answer.len() + 1];
+        self.retry(|dev| dev.write_read(read_command, &mut answer))?;
+        let offset = self.model.brightness_offset;
+        match answer.split_last() {
+            Some((&num, start))
+                if start == self.model.read_answer
+                    && (offset..=offset + self.max_brightness).contains(&num) =>
+            {
+                let brightness_num = num - offset;
+                self.brightness = Some(brightness_num);
+                Ok(brightness_num)
+            }
+            _ => Err(unsupported(format!(
+                "the light didn't report its brightness, got {:02x?}",
+                answer
+            )))?,
+        }
     }
 
     /// Turn on the numpad light. If the numpad is not turned on, setting the brightness won't do anything.
     pub fn turn_on(&mut self) -> Result<()> {
-        self.write(Self::TURN_ON)
+        self.write(self.model.turn_on)?;
+        self.is_on = true;
+        Ok(())
     }
 
     /// Turn off the numpad light.
     pub fn turn_off(&mut self) -> Result<()> {
-        self.write(Self::TURN_OFF)
+        self.write(self.model.turn_off)?;
+        self.is_on = false;
+        Ok(())
+    }
+
+    /// Whether the light was turned on by [NumpadLight::turn_on], and not turned off since.
+    pub fn is_on(&self) -> bool {
+        self.is_on
+    }
+
+    /// Whether the light is on, its brightness level and the highest level it has, all at once. This
+    /// is what was last written to the light, which isn't read back.
+    pub fn status(&self) -> LightStatus {
+        LightStatus {
+            on: self.is_on,
+            level: self.brightness.unwrap_or(0),
+            max: self.max_brightness,
+        }
+    }
+
+    /// Turn the light off if it's on, or on if it's off, see [NumpadLight::is_on].
+    pub fn toggle(&mut self) -> Result<()> {
+        if self.is_on {
+            self.turn_off()
+        } else {
+            self.turn_on()
+        }
     }
 
     /// Set the brightness level, assuming the numpad is turned on.
-    /// Will return an error if the given brightness num is greater than the MAX_BRIGHTNESS constant,
+    /// Will return an error if the given brightness num is greater than [NumpadLight::max_brightness],
     /// or if some IO error occured.
+    /// This stops any fade in progress.
     pub fn set_brightness(&mut self, brightness_num: u8) -> Result<()> {
-        if brightness_num > MAX_BRIGHTNESS {
-            Err(std::io::Error::new(
-                ErrorKind::Other,
-                "brightness number exceeded; max is 14",
+        self.check_brightness(brightness_num)?;
+        self.fade = None;
+        self.write_brightness(brightness_num)
+    }
+
+    /// Like [NumpadLight::set_brightness], but brightness nums greater than the maximum set the
+    /// maximum brightness instead of being an error; only IO errors are returned.
+    pub fn set_brightness_clamped(&mut self, brightness_num: u8) -> Result<()> {
+        self.set_brightness(brightness_num.min(self.max_brightness))
+    }
+
+    /// The highest brightness level the light has, which is the model's.
+    pub fn max_brightness(&self) -> u8 {
+        self.max_brightness
+    }
+
+    fn check_brightness(&self, brightness_num: u8) -> Result<()> {
+        if brightness_num > self.max_brightness {
+            Err(std::io::Error::other(format!(
+                "brightness number exceeded; max is {}",
+                self.max_brightness
+            )))?;
+        }
+        Ok(())
+    }
+
+    fn write_brightness(&mut self, brightness_num: u8) -> Result<()> {
+        self.write(brightness_num + self.model.brightness_offset)?;
+        self.brightness = Some(brightness_num);
+        Ok(())
+    }
+
+    /// Start going through the brightness levels from the current one to `target` over `duration`,
+    /// replacing any fade in progress. This doesn't block; the levels get written by calling
+    /// [NumpadLight::update_fade] at the times given by [NumpadLight::next_fade_update].
+    /// If the brightness hasn't been set yet, it is set to `target` right away.
+    pub fn fade_to(&mut self, target: u8, duration: Duration) -> Result<()> {
+        self.check_brightness(target)?;
+        match self.brightness {
+            Some(from) if from != target && !duration.is_zero() => {
+                self.fade = Some(Fade {
+                    from,
+                    to: target,
+                    start: Instant::now(),
+                    duration,
+                });
+                self.update_fade()
+            }
+            _ => self.set_brightness(target),
+        }
+    }
+
+    /// When [NumpadLight::update_fade] should be called next, or None if no fade is in progress.
+    pub fn next_fade_update(&self) -> Option<Instant> {
+        let fade = self.fade?;
+        Some(fade.next_step(self.brightness.unwrap_or(fade.from)))
+    }
+
+    /// Write the brightness level which the fade in progress has reached, if it changed.
+    pub fn update_fade(&mut self) -> Result<()> {
+        let Some(fade) = self.fade else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        let level = fade.level_at(now);
+        if level == fade.to {
+            self.fade = None;
+        }
+        if self.brightness != Some(level)
+            && let Err(error) = self.write_brightness(level)
+        {
+            // give up rather than retrying right away over and over
+            self.fade = None;
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    /// Set the brightness as a percentage, rounded to the nearest brightness level, see [percent_to_level].
+    /// With the default gamma the percentages map linearly to the levels; since the eye is more sensitive
+    /// to differences between dim levels, a higher gamma (see [NumpadLight::set_gamma]) makes them
+    /// percentages of the perceived brightness, with e.g. 50% well below the middle level.
+    /// Will return an error if the percentage is greater than 100, or if some IO error occured.
+    pub fn set_brightness_percent(&mut self, percent: u8) -> Result<()> {
+        if percent > 100 {
+            Err(std::io::Error::other(
+                "brightness percentage exceeded; max is 100",
             ))?;
         }
 
-        self.write(brightness_num + Self::BRIGHTNESS_OFFSET)
+        self.set_brightness(percent_to_level(percent, self.max_brightness, self.gamma))
+    }
+
+    /// The brightness as a percentage, as
+    /// [NumpadLight::set_brightness_percent] takes it, or None if the brightness hasn't been set yet.
+    pub fn brightness_percent(&self) -> Option<u8> {
+        self.brightness
+            .map(|level| level_to_percent(level, self.max_brightness, self.gamma))
+    }
+}
+
+/// Whether the error only means that the bus was busy, so that trying again may work.
+fn is_busy(error: &LinuxI2CError) -> bool {
+    let errno = match error {
+        LinuxI2CError::Errno(errno) => Some(*errno),
+        LinuxI2CError::Io(error) => error.raw_os_error(),
+    };
+    matches!(errno, Some(libc::EBUSY | libc::EAGAIN))
+}
+
+impl<D: LightTransport> Drop for NumpadLight<D> {
+    /// Turn the light off if it was turned on, so that it doesn't stay on without anyone to turn it off.
+    fn drop(&mut self) {
+        if self.is_on {
+            // there's no one left to report the error to
+            let _ = self.write(self.model.turn_off);
+        }
+    }
+}
+
+/// The brightness level out of `max_brightness` which the percentage maps to with the gamma: the
+/// percentage is raised to the power of the gamma, then rounded to the nearest level. Percentages above
+/// 100 are taken as 100.
+pub fn percent_to_level(percent: u8, max_brightness: u8, gamma: f64) -> u8 {
+    ((percent.min(100) as f64 / 100.0).powf(gamma) * max_brightness as f64).round() as u8
+}
+
+fn level_to_percent(level: u8, max_brightness: u8, gamma: f64) -> u8 {
+    ((level as f64 / max_brightness.max(1) as f64).powf(gamma.recip()) * 100.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn light() -> NumpadLight<RecordingTransport> {
+        NumpadLight::with_transport(RecordingTransport::default(), LightModel::default_model())
+    }
+
+    /// The default model's command with the given value.
+    fn frame(value: u8) -> Vec<u8> {
+        vec![
+            0x05, 0x00, 0x3d, 0x03, 0x06, 0x00, 0x07, 0x00, 0x0d, 0x14, 0x03, value, 0xad,
+        ]
+    }
+
+    #[test]
+    fn read_brightness_parses_the_answer() {
+        let mut light = light();
+        light.transport_mut().answer = vec![0x07, 0x00, 0x0d, 0x14, 0x03, 5 + 65, 0xad];
+        assert_eq!(light.read_brightness().unwrap(), 5);
+        assert_eq!(
+            light.transport().writes,
+            [[0x05, 0x00, 0x3d, 0x02, 0x06, 0x00]]
+        );
+        assert_eq!(light.status().level, 5);
+    }
+
+    #[test]
+    fn read_brightness_rejects_other_answers() {
+        let mut light = light();
+        for answer in [
+            vec![],
+            vec![0x07, 0x00, 0x0d, 0x14, 0x04, 5 + 65],
+            vec![0x07, 0x00, 0x0d, 0x14, 0x03, 1],
+            vec![0x07, 0x00, 0x0d, 0x14, 0x03, MAX_BRIGHTNESS + 66],
+        ] {
+            light.transport_mut().answer = answer.clone();
+            let Err(LinuxI2CError::Io(error)) = light.read_brightness() else {
+                panic!("{:02x?} was taken for a brightness", answer);
+            };
+            assert_eq!(error.kind(), std::io::ErrorKind::Unsupported);
+        }
+    }
+
+    #[test]
+    fn set_brightness_writes_the_level_frame() {
+        let mut light = light();
+        light.set_brightness(3).unwrap();
+        assert_eq!(light.transport().writes, [frame(3 + 65)]);
+        assert_eq!(light.status().level, 3);
+        assert!(light.set_brightness(MAX_BRIGHTNESS + 1).is_err());
+        assert_eq!(light.transport().writes.len(), 1);
+    }
+
+    #[test]
+    fn turn_on_and_off_write_their_frames() {
+        let mut light = light();
+        light.turn_on().unwrap();
+        assert!(light.is_on());
+        light.turn_off().unwrap();
+        assert!(!light.is_on());
+        assert_eq!(light.transport().writes, [frame(1), frame(0)]);
+    }
+
+    /// Fails with `errno` for the first `failures` writes, then writes like a [RecordingTransport].
+    #[derive(Default)]
+    struct FlakyTransport {
+        errno: i32,
+        failures: u32,
+        attempts: u32,
+        writes: Vec<Vec<u8>>,
+    }
+
+    impl LightTransport for FlakyTransport {
+        fn write(&mut self, data: &[u8]) -> Result<()> {
+            self.attempts += 1;
+            if self.attempts <= self.failures {
+                return Err(LinuxI2CError::Errno(self.errno));
+            }
+            self.writes.push(data.to_vec());
+            Ok(())
+        }
+
+        fn write_read(&mut self, command: &[u8], _answer: &mut [u8]) -> Result<()> {
+            self.write(command)
+        }
+    }
+
+    fn flaky_light(errno: i32, failures: u32) -> NumpadLight<FlakyTransport> {
+        let transport = FlakyTransport {
+            errno,
+            failures,
+            ..FlakyTransport::default()
+        };
+        NumpadLight::with_transport(transport, LightModel::default_model())
+    }
+
+    #[test]
+    fn writes_are_retried_while_the_bus_is_busy() {
+        let mut light = flaky_light(libc::EBUSY, 2);
+        light.turn_on().unwrap();
+        assert!(light.is_on());
+        assert_eq!(light.transport().attempts, 3);
+        assert_eq!(light.transport().writes, [frame(1)]);
+
+        let mut light = flaky_light(libc::EAGAIN, DEFAULT_RETRIES);
+        light.set_brightness(3).unwrap();
+        assert_eq!(light.transport().attempts, DEFAULT_RETRIES + 1);
+        assert_eq!(light.transport().writes, [frame(3 + 65)]);
+    }
+
+    #[test]
+    fn writes_give_up_after_the_retries() {
+        let mut light = flaky_light(libc::EBUSY, u32::MAX);
+        light.set_retries(2);
+        assert!(light.turn_on().is_err());
+        assert!(!light.is_on());
+        assert_eq!(light.transport().attempts, 3);
+        assert!(light.transport().writes.is_empty());
+    }
+
+    #[test]
+    fn other_errors_are_not_retried() {
+        let mut light = flaky_light(libc::EIO, 1);
+        assert!(light.set_brightness(3).is_err());
+        assert_eq!(light.transport().attempts, 1);
+        assert_eq!(light.status().level, 0);
+    }
+
+    #[test]
+    fn brightness_percent_maps_onto_the_levels() {
+        let mut light = light();
+        assert_eq!(light.brightness_percent(), None);
+        light.set_brightness_percent(0).unwrap();
+        assert_eq!(light.status().level, 0);
+        assert_eq!(light.brightness_percent(), Some(0));
+        light.set_brightness_percent(100).unwrap();
+        assert_eq!(light.status().level, MAX_BRIGHTNESS);
+        assert_eq!(light.brightness_percent(), Some(100));
+    }
+
+    #[test]
+    fn brightness_percent_rounds_to_the_nearest_level() {
+        // each level is 100 / 7 = 14.29%, so the halfway points are at 7.14%, 21.43% and so on
+        for (percent, level) in [
+            (7, 0),
+            (8, 1),
+            (21, 1),
+            (22, 2),
+            (64, 4),
+            (65, 5),
+            (92, 6),
+            (93, 7),
+        ] {
+            let mut light = light();
+            light.set_brightness_percent(percent).unwrap();
+            assert_eq!(light.status().level, level, "{}%", percent);
+        }
+        let mut light = light();
+        light.set_brightness(3).unwrap();
+        assert_eq!(light.brightness_percent(), Some(43));
+    }
+
+    #[test]
+    fn percent_to_level_is_linear_by_default() {
+        for (percent, level) in [
+            (0, 0),
+            (10, 1),
+            (25, 2),
+            (50, 4),
+            (75, 5),
+            (90, 6),
+            (100, 7),
+        ] {
+            assert_eq!(
+                percent_to_level(percent, MAX_BRIGHTNESS, DEFAULT_GAMMA),
+                level,
+                "{}%",
+                percent
+            );
+        }
+    }
+
+    #[test]
+    fn percent_to_level_with_the_perceptual_gamma() {
+        for (percent, level) in [
+            (0, 0),
+            (10, 0),
+            (25, 0),
+            (50, 2),
+            (75, 4),
+            (90, 6),
+            (100, 7),
+        ] {
+            assert_eq!(
+                percent_to_level(percent, MAX_BRIGHTNESS, PERCEPTUAL_GAMMA),
+                level,
+                "{}%",
+                percent
+            );
+        }
+    }
+
+    #[test]
+    fn set_brightness_percent_uses_the_gamma() {
+        let mut light = light();
+        light.set_gamma(PERCEPTUAL_GAMMA).unwrap();
+        light.set_brightness_percent(50).unwrap();
+        assert_eq!(light.status().level, 2);
+        assert_eq!(light.brightness_percent(), Some(57));
+        assert!(light.set_brightness_percent(101).is_err());
+    }
+
+    #[test]
+    fn set_gamma_rejects_invalid_ones() {
+        let mut light = light();
+        for gamma in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(light.set_gamma(gamma).is_err(), "{}", gamma);
+        }
+        // the default stays in use
+        light.set_brightness_percent(50).unwrap();
+        assert_eq!(light.status().level, 4);
     }
 }
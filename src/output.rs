@@ -0,0 +1,266 @@
+//! The hardware a [NumberPad](crate::dev::NumberPad) drives, as traits so that it can be replaced,
+//! e.g. by fakes which record what they were asked to do.
+
+use std::time::{Duration, Instant};
+
+use evdev_rs::{Device, GrabMode, enums::EV_KEY};
+use i2cdev::linux::LinuxI2CError;
+
+use crate::{
+    key_simulation::{KeySimulator, UnicodeInput},
+    numpad_light::{LightStatus, LightTransport, MAX_BRIGHTNESS, NumpadLight},
+};
+
+/// Where the keys which are pressed on the numpad go.
+pub trait KeyOutput {
+    fn keys_down(&mut self, keys: &[EV_KEY]) -> std::io::Result<()>;
+    fn keys_up(&mut self, keys: &[EV_KEY]) -> std::io::Result<()>;
+    /// Press and release the keys.
+    fn keys_press(&mut self, keys: &[EV_KEY]) -> std::io::Result<()>;
+    /// Repeat the keys, which are being held.
+    fn keys_repeat(&mut self, keys: &[EV_KEY]) -> std::io::Result<()>;
+    /// Click a mouse button, e.g. BTN_RIGHT.
+    fn button_click(&mut self, button: EV_KEY) -> std::io::Result<()> {
+        self.keys_press(&[button])
+    }
+    /// Type a character which has no key of its own, see [UnicodeInput]. The keys it needs have to be
+    /// among the ones that can be sent, see [UnicodeInput::keys].
+    fn type_unicode(&mut self, character: char, input: &UnicodeInput) -> std::io::Result<()> {
+        for keys in input.sequence(character) {
+            self.keys_press(&keys)?;
+        }
+        Ok(())
+    }
+    /// Release every key which may be down; this must be harmless if none is.
+    fn release_all(&mut self) -> std::io::Result<()>;
+}
+
+/// The numpad's light; see [NumpadLight] for what the methods do.
+pub trait LightOutput {
+    fn turn_on(&mut self) -> Result<(), LinuxI2CError>;
+    fn turn_off(&mut self) -> Result<(), LinuxI2CError>;
+    fn is_on(&self) -> bool;
+    fn status(&self) -> LightStatus;
+    fn toggle(&mut self) -> Result<(), LinuxI2CError> {
+        if self.is_on() {
+            self.turn_off()
+        } else {
+            self.turn_on()
+        }
+    }
+    fn set_brightness(&mut self, brightness: u8) -> Result<(), LinuxI2CError>;
+    fn max_brightness(&self) -> u8 {
+        MAX_BRIGHTNESS
+    }
+    fn fade_to(&mut self, target: u8, duration: Duration) -> Result<(), LinuxI2CError>;
+    fn next_fade_update(&self) -> Option<Instant>;
+    fn update_fade(&mut self) -> Result<(), LinuxI2CError>;
+}
+
+/// Something which can take the touchpad's events away from everyone else while the numpad is used.
+pub trait GrabControl {
+    fn grab(&mut self, mode: GrabMode) -> std::io::Result<()>;
+}
+
+impl KeyOutput for KeySimulator {
+    fn keys_down(&mut self, keys: &[EV_KEY]) -> std::io::Result<()> {
+        KeySimulator::keys_down(self, keys)
+    }
+
+    fn keys_up(&mut self, keys: &[EV_KEY]) -> std::io::Result<()> {
+        KeySimulator::keys_up(self, keys)
+    }
+
+    fn keys_press(&mut self, keys: &[EV_KEY]) -> std::io::Result<()> {
+        KeySimulator::keys_press(self, keys)
+    }
+
+    fn keys_repeat(&mut self, keys: &[EV_KEY]) -> std::io::Result<()> {
+        KeySimulator::keys_repeat(self, keys)
+    }
+
+    fn button_click(&mut self, button: EV_KEY) -> std::io::Result<()> {
+        KeySimulator::button_click(self, button)
+    }
+
+    fn release_all(&mut self) -> std::io::Result<()> {
+        KeySimulator::release_all(self)
+    }
+}
+
+impl<D: LightTransport> LightOutput for NumpadLight<D> {
+    fn turn_on(&mut self) -> Result<(), LinuxI2CError> {
+        NumpadLight::turn_on(self)
+    }
+
+    fn turn_off(&mut self) -> Result<(), LinuxI2CError> {
+        NumpadLight::turn_off(self)
+    }
+
+    fn is_on(&self) -> bool {
+        NumpadLight::is_on(self)
+    }
+
+    fn status(&self) -> LightStatus {
+        NumpadLight::status(self)
+    }
+
+    fn toggle(&mut self) -> Result<(), LinuxI2CError> {
+        NumpadLight::toggle(self)
+    }
+
+    fn set_brightness(&mut self, brightness: u8) -> Result<(), LinuxI2CError> {
+        NumpadLight::set_brightness(self, brightness)
+    }
+
+    fn max_brightness(&self) -> u8 {
+        NumpadLight::max_brightness(self)
+    }
+
+    fn fade_to(&mut self, target: u8, duration: Duration) -> Result<(), LinuxI2CError> {
+        NumpadLight::fade_to(self, target, duration)
+    }
+
+    fn next_fade_update(&self) -> Option<Instant> {
+        NumpadLight::next_fade_update(self)
+    }
+
+    fn update_fade(&mut self) -> Result<(), LinuxI2CError> {
+        NumpadLight::update_fade(self)
+    }
+}
+
+impl GrabControl for Device {
+    fn grab(&mut self, mode: GrabMode) -> std::io::Result<()> {
+        Device::grab(self, mode)
+    }
+}
+
+/// Parts which record what the number pad asked them to do, for driving it with scripted events in tests.
+#[cfg(test)]
+pub(crate) mod fake {
+    use std::time::{Duration, Instant};
+
+    use evdev_rs::{GrabMode, enums::EV_KEY};
+    use i2cdev::linux::LinuxI2CError;
+
+    use super::{GrabControl, KeyOutput, LightOutput};
+    use crate::numpad_light::{LightStatus, MAX_BRIGHTNESS};
+
+    #[derive(Debug, Default)]
+    pub struct FakeTouchpad {
+        /// Whether each call grabbed rather than ungrabbed the touchpad, in order.
+        pub grabs: Vec<bool>,
+    }
+
+    impl GrabControl for FakeTouchpad {
+        fn grab(&mut self, mode: GrabMode) -> std::io::Result<()> {
+            self.grabs.push(matches!(mode, GrabMode::Grab));
+            Ok(())
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum KeyEvent {
+        Down(Vec<EV_KEY>),
+        Up(Vec<EV_KEY>),
+        Press(Vec<EV_KEY>),
+        Repeat(Vec<EV_KEY>),
+        ReleaseAll,
+    }
+
+    #[derive(Debug, Default)]
+    pub struct FakeKeys {
+        pub events: Vec<KeyEvent>,
+    }
+
+    impl KeyOutput for FakeKeys {
+        fn keys_down(&mut self, keys: &[EV_KEY]) -> std::io::Result<()> {
+            self.events.push(KeyEvent::Down(keys.to_vec()));
+            Ok(())
+        }
+
+        fn keys_up(&mut self, keys: &[EV_KEY]) -> std::io::Result<()> {
+            self.events.push(KeyEvent::Up(keys.to_vec()));
+            Ok(())
+        }
+
+        fn keys_press(&mut self, keys: &[EV_KEY]) -> std::io::Result<()> {
+            self.events.push(KeyEvent::Press(keys.to_vec()));
+            Ok(())
+        }
+
+        fn keys_repeat(&mut self, keys: &[EV_KEY]) -> std::io::Result<()> {
+            self.events.push(KeyEvent::Repeat(keys.to_vec()));
+            Ok(())
+        }
+
+        fn release_all(&mut self) -> std::io::Result<()> {
+            self.events.push(KeyEvent::ReleaseAll);
+            Ok(())
+        }
+    }
+
+    /// A light which takes on every level right away, fades included.
+    #[derive(Debug)]
+    pub struct FakeLight {
+        pub on: bool,
+        pub level: u8,
+        pub max: u8,
+    }
+
+    impl Default for FakeLight {
+        fn default() -> Self {
+            Self {
+                on: false,
+                level: 0,
+                max: MAX_BRIGHTNESS,
+            }
+        }
+    }
+
+    impl LightOutput for FakeLight {
+        fn turn_on(&mut self) -> Result<(), LinuxI2CError> {
+            self.on = true;
+            Ok(())
+        }
+
+        fn turn_off(&mut self) -> Result<(), LinuxI2CError> {
+            self.on = false;
+            Ok(())
+        }
+
+        fn is_on(&self) -> bool {
+            self.on
+        }
+
+        fn status(&self) -> LightStatus {
+            LightStatus {
+                on: self.on,
+                level: self.level,
+                max: self.max,
+            }
+        }
+
+        fn set_brightness(&mut self, brightness: u8) -> Result<(), LinuxI2CError> {
+            self.level = brightness.min(self.max);
+            Ok(())
+        }
+
+        fn max_brightness(&self) -> u8 {
+            self.max
+        }
+
+        fn fade_to(&mut self, target: u8, _duration: Duration) -> Result<(), LinuxI2CError> {
+            self.set_brightness(target)
+        }
+
+        fn next_fade_update(&self) -> Option<Instant> {
+            None
+        }
+
+        fn update_fade(&mut self) -> Result<(), LinuxI2CError> {
+            Ok(())
+        }
+    }
+}